@@ -1,25 +1,41 @@
 use std::{
-    path::PathBuf,
+    fs, mem,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Condvar, Mutex,
     },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use gelatin::image::imageops::{
-    flip_horizontal_in_place, flip_vertical_in_place, rotate180_in_place,
-    rotate270, rotate90,
+use gelatin::image::{
+    imageops::{
+        flip_horizontal_in_place, flip_vertical_in_place, rotate180_in_place,
+        rotate270, rotate90,
+    },
+    RgbaImage,
 };
 
 use crate::image_cache::image_loader::{
     complex_load_image, LoadResult, Orientation,
 };
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum ClipboardRequest {
+    /// Copy the image in the file
+    Image(PathBuf),
+    Text(String),
+    /// Save the image on the clipboard into the folder
+    Paste(PathBuf),
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum ClipboardState {
-    Pending(PathBuf),
+    Pending(ClipboardRequest),
     Succeeded,
     Failed,
+    /// The path of the pasted image, or why nothing was pasted
+    Pasted(Result<PathBuf, String>),
 }
 
 struct ClipboardRequestHandle {
@@ -56,12 +72,26 @@ impl ClipboardHandler {
     }
 
     pub fn request_copy(&mut self, target: PathBuf) -> bool {
+        self.request(ClipboardRequest::Image(target))
+    }
+
+    pub fn request_copy_text(&mut self, text: String) -> bool {
+        self.request(ClipboardRequest::Text(text))
+    }
+
+    /// Saves the image on the clipboard into `dir`. `take_pasted` returns
+    /// the path of the file once it's saved.
+    pub fn request_paste(&mut self, dir: PathBuf) -> bool {
+        self.request(ClipboardRequest::Paste(dir))
+    }
+
+    fn request(&mut self, request: ClipboardRequest) -> bool {
         {
             let mut state = self.request_handle.state.lock().unwrap();
             if let ClipboardState::Pending(..) = &*state {
                 return false;
             } else {
-                *state = ClipboardState::Pending(target);
+                *state = ClipboardState::Pending(request);
             }
         }
         // Notify the condvar after releasing the mutex
@@ -80,11 +110,23 @@ impl ClipboardHandler {
         let state = self.request_handle.state.lock().unwrap();
         match &*state {
             ClipboardState::Pending(..) => None,
-            ClipboardState::Succeeded => Some(true),
+            ClipboardState::Succeeded | ClipboardState::Pasted(_) => Some(true),
             ClipboardState::Failed => Some(false),
         }
     }
 
+    /// Returns the result of the paste once it's finished.
+    pub fn take_pasted(&self) -> Option<Result<PathBuf, String>> {
+        let mut state = self.request_handle.state.lock().unwrap();
+        match mem::replace(&mut *state, ClipboardState::Succeeded) {
+            ClipboardState::Pasted(result) => Some(result),
+            other => {
+                *state = other;
+                None
+            }
+        }
+    }
+
     fn request_handler_thread(request_handle: Arc<ClipboardRequestHandle>) {
         const WAIT_TIMEOUT: std::time::Duration =
             std::time::Duration::from_millis(50);
@@ -93,12 +135,14 @@ impl ClipboardHandler {
             eprintln!("The clipboard could not be created, error was: {}", e);
         }
         while request_handle.run_thread.load(Ordering::Acquire) {
-            let request_path;
+            let request;
             {
                 let mut state_guard = request_handle.state.lock().unwrap();
                 'wait_for_request: loop {
-                    if let ClipboardState::Pending(path) = state_guard.clone() {
-                        request_path = path;
+                    if let ClipboardState::Pending(pending) =
+                        state_guard.clone()
+                    {
+                        request = pending;
                         break 'wait_for_request;
                     } else {
                         if !request_handle.run_thread.load(Ordering::Acquire) {
@@ -118,6 +162,37 @@ impl ClipboardHandler {
                     }
                 }
             }
+            let request_path = match request {
+                ClipboardRequest::Image(path) => path,
+                ClipboardRequest::Text(text) => {
+                    let succeeded = match &mut clipboard {
+                        Ok(clipboard) => match clipboard.set_text(text) {
+                            Ok(()) => true,
+                            Err(e) => {
+                                eprintln!("Could not set the clipboard text, error was: {}", e);
+                                false
+                            }
+                        },
+                        Err(_) => false,
+                    };
+                    let mut state = request_handle.state.lock().unwrap();
+                    *state = if succeeded {
+                        ClipboardState::Succeeded
+                    } else {
+                        ClipboardState::Failed
+                    };
+                    continue;
+                }
+                ClipboardRequest::Paste(dir) => {
+                    let result = match &mut clipboard {
+                        Ok(clipboard) => save_clipboard_image(clipboard, &dir),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    let mut state = request_handle.state.lock().unwrap();
+                    *state = ClipboardState::Pasted(result);
+                    continue;
+                }
+            };
             let result = complex_load_image(&request_path, false, 0, |frame| {
                 if let LoadResult::Frame {
                     mut image,
@@ -181,6 +256,34 @@ impl ClipboardHandler {
     }
 }
 
+/// Saves the image on the clipboard as a PNG file in `dir` and returns the
+/// path of the file.
+fn save_clipboard_image(
+    clipboard: &mut arboard::Clipboard,
+    dir: &Path,
+) -> Result<PathBuf, String> {
+    let image = match clipboard.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => {
+            return Err("The clipboard doesn't contain an image".into());
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let image = RgbaImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )
+    .ok_or("The clipboard image has an invalid size")?;
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let path = dir.join(format!("pasted-{}.png", millis));
+    image.save(&path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
 impl Default for ClipboardHandler {
     fn default() -> Self {
         ClipboardHandler::new()
@@ -4,6 +4,9 @@ use crate::Version;
 
 pub struct Args {
     pub file_path: Option<String>,
+    pub validate_config: bool,
+    pub cache_readonly: bool,
+    pub config_provenance: bool,
 }
 
 const HELP: &str = "\
@@ -13,6 +16,11 @@ USAGE:
 FLAGS:
   -h, --help            Prints help information
   -v, --version         Prints version
+      --validate-config Checks the configuration file and exits
+      --cache-readonly  Uses the cache without writing changes back to it
+      --config-provenance
+                        Prints where each configured value comes from and the
+                        hash of the config, then exits
 OPTIONS:
 ARGS:
   <PATH>                The file path of the image
@@ -31,18 +39,26 @@ pub fn parse_args() -> Args {
         std::process::exit(0);
     }
 
+    let validate_config = pargs.contains("--validate-config");
+    let cache_readonly = pargs.contains("--cache-readonly");
+    let config_provenance = pargs.contains("--config-provenance");
+
     // TODO: Options
 
     // Get filename
-    match pargs.free_from_str::<String>() {
-        Ok(file_path) if !file_path.starts_with('-') => Args {
-            file_path: Some(file_path),
-        },
+    let file_path = match pargs.free_from_str::<String>() {
+        Ok(file_path) if !file_path.starts_with('-') => Some(file_path),
         Ok(_) => {
             println!("Invalid usage\n");
             print!("{}", HELP);
             std::process::exit(1);
         }
-        Err(_) => Args { file_path: None },
+        Err(_) => None,
+    };
+    Args {
+        file_path,
+        validate_config,
+        cache_readonly,
+        config_provenance,
     }
 }
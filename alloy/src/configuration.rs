@@ -9,25 +9,38 @@
 
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
-    fs,
-    path::Path,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env, fmt, fs,
+    path::{Path, PathBuf},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    event_hooks::EVENT_NAMES,
+    input_handling::{
+        is_action_name, substitute_command_parameters, MODIFIER_NAMES,
+        SET_WALLPAPER_NAME,
+    },
+    logging::expand_path,
+    state_db::StateDb,
+};
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Theme {
     Light,
     Dark,
+    /// Follows the preference of the desktop, light if it's not known
+    System,
 }
 impl Theme {
+    /// Returns the opposite of the resolved theme.
     pub fn switch_theme(self) -> Self {
         match self {
             Theme::Dark => Theme::Light,
-            Theme::Light => Theme::Dark,
+            _ => Theme::Dark,
         }
     }
 }
@@ -44,66 +57,976 @@ impl Default for Antialias {
         Antialias::Auto
     }
 }
+impl Antialias {
+    fn name(self) -> &'static str {
+        match self {
+            Antialias::Auto => "auto",
+            Antialias::Always => "always",
+            Antialias::Never => "never",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Antialias> {
+        match name {
+            "auto" => Some(Antialias::Auto),
+            "always" => Some(Antialias::Always),
+            "never" => Some(Antialias::Never),
+            _ => None,
+        }
+    }
+}
+
+/// How an image is sized to the window, set by `img_orig`, `img_fit` and
+/// `img_fit_best`.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FitMode {
+    Original,
+    Fit,
+    #[default]
+    FitBest,
+}
+impl FitMode {
+    fn name(self) -> &'static str {
+        match self {
+            FitMode::Original => "original",
+            FitMode::Fit => "fit",
+            FitMode::FitBest => "fit_best",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<FitMode> {
+        match name {
+            "original" => Some(FitMode::Original),
+            "fit" => Some(FitMode::Fit),
+            "fit_best" => Some(FitMode::FitBest),
+            _ => None,
+        }
+    }
+}
+
+/// The names of the per-file maps in the state database.
+const SCROLL_POSITIONS_MAP: &str = "scroll_positions";
+const ROTATIONS_MAP: &str = "rotations";
+const ANTIALIAS_OVERRIDES_MAP: &str = "antialias_overrides";
+const FIT_MODES_MAP: &str = "fit_modes";
+
+/// The maximum number of files for which per-file state is kept in the cache.
+const MAX_REMEMBERED_FILES: usize = 1000;
+
+/// Inserts `value` for the file at `path`. When the map is full, it first
+/// forgets the files that don't exist anymore, and then the file whose state
+/// was written the longest time ago.
+fn insert_per_file<T>(
+    map: &mut BTreeMap<String, T>,
+    order: &mut WriteOrder,
+    path: &Path,
+    value: T,
+) {
+    let key = path.to_string_lossy().into_owned();
+    if map.len() >= MAX_REMEMBERED_FILES && !map.contains_key(&key) {
+        map.retain(|path, _| Path::new(path).exists());
+        if map.len() >= MAX_REMEMBERED_FILES {
+            let oldest = map.keys().min_by_key(|k| order.get(k)).cloned();
+            map.remove(&oldest.unwrap());
+        }
+    }
+    order.stamp(&key);
+    map.insert(key, value);
+}
+
+/// The order in which the per-file state of the files was written, so that
+/// the least recently written files are forgotten first.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WriteOrder {
+    /// The stamp of the next write
+    next: u64,
+    /// The stamp of the last write of each file
+    stamps: BTreeMap<String, u64>,
+}
+impl WriteOrder {
+    fn stamp(&mut self, key: &str) {
+        self.stamps.insert(key.to_owned(), self.next);
+        self.next += 1;
+    }
+
+    /// Files written before the order was kept count as the oldest.
+    fn get(&self, key: &str) -> u64 {
+        self.stamps.get(key).copied().unwrap_or(0)
+    }
+
+    /// Keeps the later stamp of the files that both orders have.
+    fn merge_from(&mut self, other: &WriteOrder) {
+        self.next = self.next.max(other.next);
+        for (key, &stamp) in &other.stamps {
+            let entry = self.stamps.entry(key.clone()).or_insert(stamp);
+            *entry = (*entry).max(stamp);
+        }
+    }
+}
+
+/// Forgets the least recently written files until at most
+/// `MAX_REMEMBERED_FILES` are left.
+fn limit_per_file<T>(map: &mut BTreeMap<String, T>, order: &WriteOrder) {
+    if map.len() <= MAX_REMEMBERED_FILES {
+        return;
+    }
+    let mut keys: Vec<_> = map.keys().cloned().collect();
+    keys.sort_by_key(|key| order.get(key));
+    for key in &keys[..keys.len() - MAX_REMEMBERED_FILES] {
+        map.remove(key);
+    }
+}
+
+/// Merges the changes that were made to `base` in `theirs` into `ours`. If
+/// both changed the same file, `ours` wins. Files that were removed in
+/// `theirs` are removed from `ours` unless `ours` changed them.
+fn merge_per_file<K: Ord + Clone, T: Clone + PartialEq>(
+    ours: &mut BTreeMap<K, T>,
+    base: &BTreeMap<K, T>,
+    theirs: &BTreeMap<K, T>,
+) {
+    let keys: BTreeSet<&K> = base.keys().chain(theirs.keys()).collect();
+    for key in keys {
+        let theirs_value = theirs.get(key);
+        if theirs_value == base.get(key) || ours.get(key) != base.get(key) {
+            continue;
+        }
+        match theirs_value {
+            Some(value) => ours.insert(key.clone(), value.clone()),
+            None => ours.remove(key),
+        };
+    }
+}
 
 #[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CacheImageSection {
     pub fit_stretches: bool,
     pub antialiasing: Antialias,
+    #[serde(default)]
+    pub grayscale: bool,
+
+    /// The topmost visible row of images that were taller than the window,
+    /// keyed by their paths.
+    #[serde(default)]
+    pub scroll_positions: BTreeMap<String, u32>,
+    /// Clockwise rotations in degrees, only stored with `persist_rotation`.
+    #[serde(default)]
+    pub rotations: BTreeMap<String, u32>,
+    /// Clockwise rotations in degrees set with `set_folder_rotation`, keyed
+    /// by the paths of folders. The images of these folders are rotated by
+    /// this unless they have their own rotation. These are always kept in
+    /// the cache file.
+    #[serde(default)]
+    pub folder_rotation: BTreeMap<String, u16>,
+    /// Antialiasing set with `toggle_antialias_this_image`, which takes
+    /// precedence over `antialiasing` for these files.
+    #[serde(default)]
+    pub antialias_overrides: BTreeMap<String, Antialias>,
+    /// The fit mode last chosen for these files, only stored with
+    /// `remember_fit_per_file`.
+    #[serde(default)]
+    pub fit_modes: BTreeMap<String, FitMode>,
+    /// The files marked with `tag:<name>`, keyed by the name of the tag.
+    /// These are always kept in the cache file.
+    #[serde(default)]
+    pub tags: BTreeMap<String, BTreeSet<String>>,
+    #[serde(default)]
+    pub write_order: WriteOrder,
+
+    /// Holds the per-file maps instead with `state_backend = "sqlite"`. The
+    /// maps above are left as they are then, so switching back to the cache
+    /// file restores them. They are still used while the database is
+    /// read-only.
+    #[serde(skip)]
+    db: Option<StateDb>,
 }
+impl CacheImageSection {
+    /// Stores the per-file state in the database from now on. The entries of
+    /// the maps are copied into it the first time it's written to.
+    pub fn use_database(&mut self, db: StateDb) -> Result<(), String> {
+        if !db.read_only() && !db.migrated() {
+            db.insert_all(
+                SCROLL_POSITIONS_MAP,
+                self.scroll_positions
+                    .iter()
+                    .map(|(path, row)| (path.as_str(), row.to_string())),
+            )?;
+            db.insert_all(
+                ROTATIONS_MAP,
+                self.rotations.iter().map(|(path, degrees)| {
+                    (path.as_str(), degrees.to_string())
+                }),
+            )?;
+            db.insert_all(
+                ANTIALIAS_OVERRIDES_MAP,
+                self.antialias_overrides
+                    .iter()
+                    .map(|(path, aa)| (path.as_str(), aa.name().to_owned())),
+            )?;
+            db.insert_all(
+                FIT_MODES_MAP,
+                self.fit_modes.iter().map(|(path, mode)| {
+                    (path.as_str(), mode.name().to_owned())
+                }),
+            )?;
+            db.set_migrated()?;
+        }
+        self.db = Some(db);
+        Ok(())
+    }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
+    /// Returns the database if the per-file state is written to it.
+    fn writable_db(&self) -> Option<&StateDb> {
+        self.db.as_ref().filter(|db| !db.read_only())
+    }
+
+    /// Forgets the write order of the files that have no per-file state
+    /// left.
+    fn prune_write_order(&mut self) {
+        let CacheImageSection {
+            scroll_positions,
+            rotations,
+            folder_rotation,
+            antialias_overrides,
+            fit_modes,
+            write_order,
+            ..
+        } = self;
+        write_order.stamps.retain(|key, _| {
+            scroll_positions.contains_key(key)
+                || rotations.contains_key(key)
+                || folder_rotation.contains_key(key)
+                || antialias_overrides.contains_key(key)
+                || fit_modes.contains_key(key)
+        });
+    }
+
+    /// Returns the value of the file from the database if the state is
+    /// written to it. Otherwise from `entries`, or the read-only database if
+    /// it's not there.
+    fn per_file_value<T: Copy>(
+        &self,
+        entries: &BTreeMap<String, T>,
+        map: &str,
+        path: &Path,
+        parse: fn(&str) -> Option<T>,
+    ) -> Option<T> {
+        if let Some(db) = self.writable_db() {
+            return parse(&db.get(map, path)?);
+        }
+        entries
+            .get(path.to_string_lossy().as_ref())
+            .copied()
+            .or_else(|| parse(&self.db.as_ref()?.get(map, path)?))
+    }
+
+    pub fn scroll_position(&self, path: &Path) -> Option<u32> {
+        self.per_file_value(
+            &self.scroll_positions,
+            SCROLL_POSITIONS_MAP,
+            path,
+            |value| value.parse().ok(),
+        )
+    }
+
+    pub fn set_scroll_position(&mut self, path: &Path, row: u32) {
+        match self.writable_db() {
+            Some(db) => {
+                db.set(SCROLL_POSITIONS_MAP, path, Some(&row.to_string()))
+            }
+            None => insert_per_file(
+                &mut self.scroll_positions,
+                &mut self.write_order,
+                path,
+                row,
+            ),
+        }
+    }
+
+    /// Returns the rotation of the file, or the default rotation of its
+    /// folder if it doesn't have one.
+    pub fn rotation(&self, path: &Path) -> u32 {
+        self.per_file_value(&self.rotations, ROTATIONS_MAP, path, |value| {
+            value.parse().ok()
+        })
+        .unwrap_or_else(|| self.folder_rotation_of(path))
+    }
+
+    /// Returns the default rotation of the folder that the file is in.
+    pub fn folder_rotation_of(&self, path: &Path) -> u32 {
+        path.parent()
+            .and_then(|folder| {
+                self.folder_rotation.get(folder.to_string_lossy().as_ref())
+            })
+            .map_or(0, |&degrees| degrees as u32)
+    }
+
+    /// Sets the rotation of the file. It's only stored if it differs from
+    /// the default rotation of its folder.
+    pub fn set_rotation(&mut self, path: &Path, degrees: u32) {
+        let default = self.folder_rotation_of(path);
+        if let Some(db) = self.writable_db() {
+            let value = degrees.to_string();
+            db.set(
+                ROTATIONS_MAP,
+                path,
+                Some(value.as_str()).filter(|_| degrees != default),
+            );
+        } else if degrees == default {
+            self.rotations.remove(path.to_string_lossy().as_ref());
+        } else {
+            insert_per_file(
+                &mut self.rotations,
+                &mut self.write_order,
+                path,
+                degrees,
+            );
+        }
+    }
+
+    pub fn antialias_override(&self, path: &Path) -> Option<Antialias> {
+        self.per_file_value(
+            &self.antialias_overrides,
+            ANTIALIAS_OVERRIDES_MAP,
+            path,
+            Antialias::from_name,
+        )
+    }
+
+    pub fn set_antialias_override(
+        &mut self,
+        path: &Path,
+        aa: Option<Antialias>,
+    ) {
+        if let Some(db) = self.writable_db() {
+            db.set(ANTIALIAS_OVERRIDES_MAP, path, aa.map(Antialias::name));
+            return;
+        }
+        match aa {
+            Some(aa) => insert_per_file(
+                &mut self.antialias_overrides,
+                &mut self.write_order,
+                path,
+                aa,
+            ),
+            None => {
+                self.antialias_overrides
+                    .remove(path.to_string_lossy().as_ref());
+            }
+        }
+    }
+
+    pub fn fit_mode(&self, path: &Path) -> Option<FitMode> {
+        self.per_file_value(
+            &self.fit_modes,
+            FIT_MODES_MAP,
+            path,
+            FitMode::from_name,
+        )
+    }
+
+    pub fn set_fit_mode(&mut self, path: &Path, mode: Option<FitMode>) {
+        if let Some(db) = self.writable_db() {
+            db.set(FIT_MODES_MAP, path, mode.map(FitMode::name));
+            return;
+        }
+        match mode {
+            Some(mode) => insert_per_file(
+                &mut self.fit_modes,
+                &mut self.write_order,
+                path,
+                mode,
+            ),
+            None => {
+                self.fit_modes.remove(path.to_string_lossy().as_ref());
+            }
+        }
+    }
+
+    /// Sets the rotation of the images in the folder that don't have their
+    /// own. 0 removes it.
+    pub fn set_folder_rotation(&mut self, folder: &Path, degrees: u32) {
+        if degrees == 0 {
+            self.folder_rotation
+                .remove(folder.to_string_lossy().as_ref());
+        } else {
+            insert_per_file(
+                &mut self.folder_rotation,
+                &mut self.write_order,
+                folder,
+                degrees as u16,
+            );
+        }
+    }
+
+    /// Adds the file to the tag or removes it. Tags without files are
+    /// removed.
+    pub fn set_tagged(&mut self, tag: &str, path: &Path, tagged: bool) {
+        let key = path.to_string_lossy().into_owned();
+        if tagged {
+            self.tags.entry(tag.to_owned()).or_default().insert(key);
+        } else if let Some(files) = self.tags.get_mut(tag) {
+            files.remove(&key);
+            if files.is_empty() {
+                self.tags.remove(tag);
+            }
+        }
+    }
+}
+
+/// What happens when the file that's already shown is opened again.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ReopenSame {
+    /// The zoom and the position are reset.
+    Reset,
+    /// Nothing changes.
+    #[default]
+    Keep,
+    /// The file is decoded again, keeping the zoom and the position.
+    Reload,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct ConfigImageSection {
     pub antialiasing: Option<String>,
+    /// How `set_wallpaper` fits the image onto the desktop. One of "fit",
+    /// "fill", "stretch" or "center".
+    pub wallpaper_mode: Option<String>,
+    /// Restore the scroll position of images taller than the window when
+    /// they are opened again.
+    pub remember_scroll: Option<bool>,
+    pub reopen_same: Option<ReopenSame>,
+    /// Rotate and flip images according to their EXIF orientation. Defaults
+    /// to true.
+    pub auto_orient: Option<bool>,
+    /// Remember the rotation set by `rotate_cw` and `rotate_ccw` for each
+    /// file.
+    pub persist_rotation: Option<bool>,
+    /// Convert images with an embedded ICC profile to sRGB before showing
+    /// them. Requires the `color-management` feature.
+    pub color_management: Option<bool>,
+    /// Images wider or taller than this many pixels are downscaled to fit
+    /// within it, keeping the aspect ratio. JPEG images are decoded at a
+    /// smaller size directly, other formats are decoded fully first. The
+    /// `load_full_resolution` action loads the current image at its original
+    /// size.
+    pub max_dimension: Option<u32>,
+    /// Decode JPEG images at 1/2, 1/4 or 1/8 of their size while browsing,
+    /// which is much faster for large files. The image is decoded again at
+    /// full quality once it has been shown for a moment.
+    pub jpeg_decode_scale: Option<u8>,
+    /// The EXIF fields that `copy_metadata` copies, like `["Model",
+    /// "DateTimeOriginal"]`. Every field is copied by default.
+    pub metadata_fields: Option<Vec<String>>,
+    /// The page that `open_location` opens for the GPS coordinates of the
+    /// image. `${lat}` and `${lon}` are replaced by the latitude and the
+    /// longitude in degrees. Defaults to OpenStreetMap.
+    pub maps_url_template: Option<String>,
+    /// What `toggle_histogram` shows.
+    pub histogram_mode: Option<HistogramMode>,
+    /// A program that makes an image of the files that can't be decoded. It
+    /// writes the image to its standard output, or saves it as a PNG to the
+    /// `${out}` path of `thumbnail_args`, which is in a folder that only the
+    /// user can access. The program is stopped after 30 seconds.
+    pub thumbnail_command: Option<String>,
+    /// The arguments of `thumbnail_command`. `${img}` is replaced by the
+    /// path of the file and `${out}` by the path to save the image to.
+    pub thumbnail_args: Option<Vec<String>>,
+    /// Files with these extensions are always passed to `thumbnail_command`,
+    /// and are listed even if they aren't images.
+    pub thumbnail_extensions: Option<Vec<String>>,
+    /// Remember the fit mode chosen for each file and apply it when the file
+    /// is opened again. `reset_fit_override` forgets it.
+    pub remember_fit_per_file: Option<bool>,
+}
+
+/// The levels that the histogram of `toggle_histogram` counts.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HistogramMode {
+    #[default]
+    Luminance,
+    /// The red, green and blue channels, each drawn in its color
+    Rgb,
 }
 
+/// Used when `maps_url_template` is not set.
+pub const DEFAULT_MAPS_URL_TEMPLATE: &str =
+    "https://www.openstreetmap.org/?mlat=${lat}&mlon=${lon}#map=15/${lat}/${lon}";
+
+/// The smallest window width allowed when the config doesn't specify one.
+pub const DEFAULT_MIN_WIN_W: u32 = 240;
+/// The smallest window height allowed when the config doesn't specify one.
+pub const DEFAULT_MIN_WIN_H: u32 = 160;
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CacheWindowSection {
     pub dark: bool,
+    /// The theme follows the desktop, `dark` is what it was last resolved to
+    #[serde(default)]
+    pub system_theme: bool,
     pub win_w: u32,
     pub win_h: u32,
     pub win_x: i32,
     pub win_y: i32,
+    /// The name of the palette selected with `cycle_palette`
+    #[serde(default)]
+    pub palette: Option<String>,
+    /// Set by `toggle_bottom_bar` in the windowed mode, overriding
+    /// `show_bottom_bar`
+    #[serde(default)]
+    pub bottom_bar: Option<BarToggle>,
+    /// Set by `toggle_bottom_bar` in fullscreen, overriding
+    /// `bottom_bar_in_fullscreen`
+    #[serde(default)]
+    pub bottom_bar_in_fullscreen: Option<BarToggle>,
 }
 impl Default for CacheWindowSection {
     fn default() -> Self {
         Self {
             dark: false,
+            system_theme: false,
             win_w: 580,
             win_h: 558,
             win_x: 64,
             win_y: 64,
+            palette: None,
+            bottom_bar: None,
+            bottom_bar_in_fullscreen: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// The bottom bar state chosen with `toggle_bottom_bar`. It only overrides
+/// the configuration while the configured value is still the one it was
+/// toggled against, so that editing the configuration takes effect.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct BarToggle {
+    pub shown: bool,
+    pub configured: Option<bool>,
+}
+
+impl BarToggle {
+    /// Returns the state the bar starts in, and clears `toggle` if the
+    /// configured value changed since it was set.
+    pub fn resolve(
+        toggle: &mut Option<BarToggle>,
+        configured: Option<bool>,
+        default: bool,
+    ) -> bool {
+        if toggle.is_some_and(|t| t.configured != configured) {
+            *toggle = None;
+        }
+        match toggle {
+            Some(toggle) => toggle.shown,
+            None => configured.unwrap_or(default),
+        }
+    }
+}
+
+/// An RGBA color written as `"#rgb"`, `"#rrggbb"` or `"#rrggbbaa"`.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub [f32; 4]);
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_color(&value).map(Color)
+    }
+}
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        let [r, g, b, a] = color.0.map(|c| (c * 255.0).round() as u8);
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+/// Parses a color written as `"#rgb"`, `"#rrggbb"` or `"#rrggbbaa"` into
+/// RGBA components between 0 and 1.
+pub fn parse_color(text: &str) -> Result<[f32; 4], String> {
+    let invalid = || {
+        format!(
+            "invalid color {:?}, expected \"#rgb\", \"#rrggbb\" or \"#rrggbbaa\"",
+            text
+        )
+    };
+    let hex = text.strip_prefix('#').ok_or_else(invalid)?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let digits: Vec<u8> = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).map(|c| c as u8).collect(),
+        6 | 8 => hex.bytes().collect(),
+        _ => return Err(invalid()),
+    };
+    let mut color = [1.0; 4];
+    for (component, pair) in color.iter_mut().zip(digits.chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        let value = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        *component = value as f32 / 255.0;
+    }
+    Ok(color)
+}
+
+/// A set of colors that `cycle_palette` switches between.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedPalette {
+    pub name: String,
+    /// Determines the icons and the colors that the palette doesn't set.
+    /// Defaults to the current theme.
+    pub theme: Option<Theme>,
+    pub background: Option<Color>,
+    pub bottom_bar: Option<Color>,
+}
+
+/// A window dimension, either in pixels or as a percentage of the work area of
+/// the monitor like `"80%"`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "WindowLengthRepr", into = "WindowLengthRepr")]
+pub enum WindowLength {
+    Pixels(u32),
+    Percent(u32),
+}
+impl WindowLength {
+    /// Returns the length in pixels. Percentages are taken of `available`;
+    /// they resolve to `None` when it's not known or when the percentage is
+    /// not between 1 and 100.
+    pub fn resolve(self, available: Option<u32>) -> Option<u32> {
+        match self {
+            WindowLength::Pixels(pixels) => Some(pixels),
+            WindowLength::Percent(percent) if percent_valid(percent) => {
+                available.map(|len| (len as u64 * percent as u64 / 100) as u32)
+            }
+            WindowLength::Percent(_) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum WindowLengthRepr {
+    Pixels(u32),
+    Text(String),
+}
+impl TryFrom<WindowLengthRepr> for WindowLength {
+    type Error = String;
+
+    fn try_from(repr: WindowLengthRepr) -> Result<Self, Self::Error> {
+        match repr {
+            WindowLengthRepr::Pixels(pixels) => Ok(WindowLength::Pixels(pixels)),
+            WindowLengthRepr::Text(text) => text
+                .trim()
+                .strip_suffix('%')
+                .and_then(|percent| percent.trim_end().parse().ok())
+                .map(WindowLength::Percent)
+                .ok_or_else(|| {
+                    format!(
+                        "expected a number of pixels or a percentage like \"80%\", found {:?}",
+                        text
+                    )
+                }),
+        }
+    }
+}
+
+impl From<WindowLength> for WindowLengthRepr {
+    fn from(length: WindowLength) -> Self {
+        match length {
+            WindowLength::Pixels(pixels) => WindowLengthRepr::Pixels(pixels),
+            WindowLength::Percent(percent) => {
+                WindowLengthRepr::Text(format!("{}%", percent))
+            }
+        }
+    }
+}
+
+fn percent_valid(percent: u32) -> bool {
+    (1..=100).contains(&percent)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConfigWindowSection {
     pub start_fullscreen: Option<bool>,
     pub start_maximized: Option<bool>,
     pub show_bottom_bar: Option<bool>,
+    /// Keep the bottom bar shown in fullscreen. Independent of
+    /// `show_bottom_bar`, which applies to the windowed mode. Defaults to
+    /// false.
+    pub bottom_bar_in_fullscreen: Option<bool>,
+    /// Format strings shown in the bottom bar, like `"${index}/${count}"`.
+    /// See `BOTTOM_BAR_PLACEHOLDERS`.
+    pub bottom_bar_fields: Option<Vec<String>>,
+    /// Goes between the `bottom_bar_fields`, defaults to `" | "`.
+    pub bottom_bar_separator: Option<String>,
+    /// The unit of `${size}`, defaults to `binary`.
+    pub size_unit: Option<SizeUnit>,
+    /// The most digits shown after the decimal point of `${size}`.
+    pub size_precision: Option<u32>,
     pub theme: Option<Theme>,
     pub use_last_window_area: Option<bool>,
-    pub win_w: Option<u32>,
-    pub win_h: Option<u32>,
+    pub win_w: Option<WindowLength>,
+    pub win_h: Option<WindowLength>,
     pub win_x: Option<i32>,
     pub win_y: Option<i32>,
+    pub min_width: Option<u32>,
+    pub min_height: Option<u32>,
+    /// Pan and zoom in fixed steps instead of accelerating while the key is
+    /// held. Defaults to the preference of the desktop if it's known.
+    pub reduce_motion: Option<bool>,
+    /// The action that runs instead of quitting when the window is closed.
+    /// Closing the window again within two seconds quits.
+    pub on_close: Option<String>,
+    /// Quit after this many seconds without keyboard or mouse input, or run
+    /// `on_close` instead if it's set. 0 disables it, which is the default.
+    pub idle_quit_secs: Option<u64>,
+    /// The palettes that `cycle_palette` steps through.
+    pub palettes: Option<Vec<NamedPalette>>,
+    /// While the window is being resized, the fitted image is only scaled
+    /// along with the window, and fitted again once the size hasn't changed
+    /// for this many milliseconds. 0 fits it on every change.
+    pub resize_debounce_ms: Option<u64>,
+    /// Show the position of the image in the folder, like "12 / 340", over
+    /// the picture. It hides when the mouse hasn't moved for a while.
+    pub progress_overlay: Option<bool>,
+    /// The corner of the picture that `progress_overlay` is shown in.
+    pub progress_overlay_position: Option<Corner>,
+}
+
+/// Goes between the parts of the bottom bar when `bottom_bar_separator` is
+/// not set.
+pub const DEFAULT_BOTTOM_BAR_SEPARATOR: &str = " | ";
+
+impl ConfigWindowSection {
+    /// Returns the separator that goes between the parts of the bottom bar.
+    pub fn bottom_bar_separator(&self) -> &str {
+        self.bottom_bar_separator
+            .as_deref()
+            .unwrap_or(DEFAULT_BOTTOM_BAR_SEPARATOR)
+    }
+
+    /// Returns the `bottom_bar_fields` with their placeholders substituted.
+    pub fn format_bottom_bar_fields(
+        &self,
+        values: &HashMap<&str, &str>,
+    ) -> Vec<String> {
+        self.bottom_bar_fields
+            .iter()
+            .flatten()
+            .map(|field| substitute_command_parameters(field, values))
+            .collect()
+    }
+}
+
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+/// Used when `resize_debounce_ms` is not set.
+pub const DEFAULT_RESIZE_DEBOUNCE_MS: u64 = 30;
+/// The longest allowed `resize_debounce_ms`.
+pub const MAX_RESIZE_DEBOUNCE_MS: u64 = 1000;
+
+/// Determines when the contents of a folder are listed.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Enumeration {
+    /// The folder is listed before the first image is shown.
+    #[default]
+    Eager,
+    /// When opening a file, it's shown right away while the rest of its
+    /// folder is listed in the background. The images are added as they are
+    /// found, and navigating past the ones found so far waits for the rest.
+    Lazy,
+}
+
+/// What happens to the list of files when files or folders are dropped onto
+/// the window.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DropBehavior {
+    /// The dropped item is opened along with its folder. When several items
+    /// are dropped at once, the rest are appended.
+    #[default]
+    ReplacePlaylist,
+    /// The dropped items are added after the current files.
+    AppendPlaylist,
+    /// The folder of the dropped item is opened.
+    OpenFolder,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ConfigDirectorySection {
+    pub enumeration: Option<Enumeration>,
+    pub drop_behavior: Option<DropBehavior>,
+    /// Include hidden files and folders when listing a folder. Defaults to
+    /// false, hidden files are still shown when they are opened directly.
+    pub show_hidden: Option<bool>,
+    /// Descend into symlinked folders when listing subfolders, visiting each
+    /// folder once so that links can't form a loop. Defaults to false, which
+    /// skips symlinked folders. Symlinked files are always listed. The images
+    /// found through a link are remembered in the cache by the path through
+    /// the link.
+    pub follow_symlinks: Option<bool>,
+    /// The image or folder that's opened when no path is given on the
+    /// command line. A leading `~` and environment variables are expanded.
+    pub home: Option<String>,
+    /// What happens when the file given on the command line doesn't exist
+    pub missing_file: Option<MissingFile>,
+    /// Where `paste_image` saves the images. A leading `~` and environment
+    /// variables are expanded. Defaults to a folder in the temporary folder
+    /// of the system. The pasted images are never deleted by Alloy.
+    pub paste_dir: Option<String>,
+    /// Whether a pasted image replaces the list of files or is added to it.
+    /// Defaults to `replace_playlist`.
+    pub paste_target: Option<PasteTarget>,
+    /// Match the case of the letters typed into `goto_file`. Defaults to
+    /// false.
+    pub fuzzy_case_sensitive: Option<bool>,
+}
+
+/// What happens to the list of files when an image is pasted.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PasteTarget {
+    /// The pasted image is opened along with the other pasted images.
+    #[default]
+    ReplacePlaylist,
+    /// The pasted image is added after the current files.
+    AppendPlaylist,
+}
+
+/// What happens when the file given on the command line doesn't exist.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingFile {
+    /// Exit with an error message
+    Error,
+    /// Open the folder of the file if the folder exists, otherwise start
+    /// with an empty window
+    #[default]
+    OpenFolder,
+    /// Start with an empty window
+    Blank,
+}
+
+impl ConfigDirectorySection {
+    /// Returns the expanded `paste_dir`, or the default folder if it's not
+    /// set.
+    pub fn paste_dir(&self) -> PathBuf {
+        match self.paste_dir {
+            Some(ref dir) => expand_path(dir),
+            None => env::temp_dir().join("alloy-pastes"),
+        }
+    }
+
+    /// Returns the expanded `home`, or `None` if it's not set or doesn't
+    /// exist.
+    pub fn home_path(&self) -> Option<PathBuf> {
+        let path = expand_path(self.home.as_deref()?);
+        if path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ConfigPlaybackSection {
+    /// Pause the slideshow when a key is pressed or the mouse is moved.
+    pub slideshow_pause_on_input: Option<bool>,
+    /// Resume the paused slideshow after this many seconds without input. If
+    /// not set, only the `slideshow_resume` action resumes it.
+    pub slideshow_resume_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ConfigLoggingSection {
+    /// One of "error", "warn", "info", "debug" or "trace".
+    pub level: Option<String>,
+    /// Logs go to this file instead of stderr. A leading `~` and environment
+    /// variables like `$HOME` are expanded.
+    pub file: Option<String>,
+    /// The log file is moved to `<file>.1` when it grows larger than this,
+    /// defaults to 1 MiB.
+    pub max_bytes: Option<u64>,
+}
+
+/// Where the state that's remembered for each file is stored.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StateBackend {
+    /// In the cache file along with the rest of the cache.
+    #[default]
+    Toml,
+    /// In an SQLite database next to the cache file, which scales to many
+    /// more files. Requires the `sqlite` feature.
+    Sqlite,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ConfigCacheSection {
+    /// Use the state stored in the cache without ever writing to it. This is
+    /// also enabled by the `--cache-readonly` flag.
+    pub read_only: Option<bool>,
+    pub state_backend: Option<StateBackend>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct ConfigUpdateSection {
     pub check_updates: bool,
+    /// Where the latest release is fetched from instead of GitHub, for
+    /// mirrors. It has to answer like the releases API of GitHub, with a
+    /// JSON object containing the `tag_name` of the latest release.
+    pub update_url: Option<String>,
 }
 impl Default for ConfigUpdateSection {
     fn default() -> Self {
         Self {
             check_updates: true,
+            update_url: None,
         }
     }
 }
 
+/// After a failed update check, the next check waits this long, doubled for
+/// every further failure up to the usual day.
+const UPDATE_RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+
 #[derive(Default, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CacheUpdateSection {
     pub last_checked: u64,
+    /// The number of update checks that failed in a row
+    #[serde(default)]
+    pub failed_checks: u32,
 }
 
 impl CacheUpdateSection {
@@ -112,7 +1035,13 @@ impl CacheUpdateSection {
             .duration_since(UNIX_EPOCH + Duration::from_secs(self.last_checked))
             .unwrap_or_else(|_| Duration::from_secs(0));
 
-        duration > Duration::from_secs(60 * 60 * 24) // 24 hours
+        let interval = match self.failed_checks {
+            0 => UPDATE_CHECK_INTERVAL,
+            failed => UPDATE_RETRY_INTERVAL
+                .saturating_mul(1 << (failed - 1).min(5))
+                .min(UPDATE_CHECK_INTERVAL),
+        };
+        duration > interval
     }
 
     pub fn set_update_check_time(&mut self) {
@@ -120,6 +1049,14 @@ impl CacheUpdateSection {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_else(|_| Duration::from_secs(0))
             .as_secs();
+        self.failed_checks = 0;
+    }
+
+    /// Records that the release couldn't be fetched, so that the next check
+    /// is attempted sooner than after a successful one.
+    pub fn set_update_check_failed(&mut self) {
+        self.set_update_check_time();
+        self.failed_checks = self.failed_checks.saturating_add(1);
     }
 }
 
@@ -135,6 +1072,19 @@ pub struct Cache {
     pub window: CacheWindowSection,
     pub updates: CacheUpdateSection,
     pub image: CacheImageSection,
+
+    /// When set, the cache is still loaded and updated in memory but `save`
+    /// doesn't write it back to the disk.
+    #[serde(skip)]
+    pub read_only: bool,
+    /// The modification time of the file when it was loaded, to find out if
+    /// another window saved it since
+    #[serde(skip)]
+    loaded_modified: Option<SystemTime>,
+    /// The image section as it was loaded, which tells what changed in this
+    /// window and what changed in the file when another window saved it.
+    #[serde(skip)]
+    loaded_image: CacheImageSection,
 }
 impl From<IncompleteCache> for Cache {
     fn from(cache: IncompleteCache) -> Self {
@@ -142,20 +1092,27 @@ impl From<IncompleteCache> for Cache {
             window: cache.window.unwrap_or_default(),
             updates: cache.updates.unwrap_or_default(),
             image: cache.image.unwrap_or_default(),
+            read_only: false,
+            loaded_modified: None,
+            loaded_image: CacheImageSection::default(),
         }
     }
 }
 impl Cache {
     pub fn theme(&self) -> Theme {
-        if self.window.dark {
+        if self.window.system_theme {
+            Theme::System
+        } else if self.window.dark {
             Theme::Dark
         } else {
             Theme::Light
         }
     }
 
-    pub fn set_theme(&mut self, theme: Theme) {
-        self.window.dark = theme == Theme::Dark;
+    /// Remembers `theme`, which is shown as `resolved`.
+    pub fn set_theme(&mut self, theme: Theme, resolved: Theme) {
+        self.window.system_theme = theme == Theme::System;
+        self.window.dark = resolved == Theme::Dark;
     }
 
     pub fn load<P: AsRef<Path>>(file_path: P) -> Result<Cache, String> {
@@ -166,34 +1123,224 @@ impl Cache {
         let result: IncompleteCache =
             toml::from_str(&cfg_str).map_err(|e| format!("{}", e))?;
         //println!("Read cache from file:\n{:#?}", result);
-        Ok(result.into())
+        let mut cache: Cache = result.into();
+        cache.loaded_modified = modified_time(file_path);
+        cache.loaded_image = cache.image.clone();
+        Ok(cache)
     }
 
+    /// Writes the cache to the file, unless the cache is read-only.
+    ///
+    /// If another window saved the file after this cache was loaded, the
+    /// per-file state that it saved is merged into this one first.
     pub fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<(), String> {
+        if self.read_only {
+            return Ok(());
+        }
+        if let Some(ref db) = self.image.db {
+            db.flush();
+        }
         let file_path = file_path.as_ref();
-        let string = toml::to_string(self).map_err(|e| format!("{}", e))?;
-        fs::write(file_path, string).map_err(|_| {
-            format!("Could not write to cache file {:?}", file_path)
-        })?;
+        let changed_on_disk = modified_time(file_path).is_some()
+            && modified_time(file_path) != self.loaded_modified;
+        let mut to_write = self.clone();
+        if changed_on_disk {
+            if let Ok(on_disk) = Cache::load(file_path) {
+                to_write.merge_from(&on_disk);
+            }
+        }
+        to_write.image.prune_write_order();
+        let string =
+            toml::to_string(&to_write).map_err(|e| format!("{}", e))?;
+        // Written to a separate file first, so that a window that saves at
+        // the same time never reads a partially written cache
+        let temp_path = file_path.with_extension("toml.tmp");
+        fs::write(&temp_path, string)
+            .and_then(|_| fs::rename(&temp_path, file_path))
+            .map_err(|_| {
+                format!("Could not write to cache file {:?}", file_path)
+            })?;
         Ok(())
     }
+
+    /// Merges the per-file state and the tags that `other` changed since
+    /// this cache was loaded. Where both changed the same file, this cache
+    /// wins. Everything else is kept as it is in this cache, except for the
+    /// time of the last update check if `other` checked later.
+    pub fn merge_from(&mut self, other: &Cache) {
+        let image = &mut self.image;
+        let base = &self.loaded_image;
+        let theirs = &other.image;
+        merge_per_file(
+            &mut image.scroll_positions,
+            &base.scroll_positions,
+            &theirs.scroll_positions,
+        );
+        merge_per_file(
+            &mut image.rotations,
+            &base.rotations,
+            &theirs.rotations,
+        );
+        merge_per_file(
+            &mut image.folder_rotation,
+            &base.folder_rotation,
+            &theirs.folder_rotation,
+        );
+        merge_per_file(
+            &mut image.antialias_overrides,
+            &base.antialias_overrides,
+            &theirs.antialias_overrides,
+        );
+        merge_per_file(
+            &mut image.fit_modes,
+            &base.fit_modes,
+            &theirs.fit_modes,
+        );
+        // Each file of a tag is merged on its own
+        let tagged = |tags: &BTreeMap<String, BTreeSet<String>>| {
+            let mut tagged = BTreeMap::new();
+            for (tag, files) in tags {
+                for file in files {
+                    tagged.insert((tag.clone(), file.clone()), ());
+                }
+            }
+            tagged
+        };
+        let mut ours_tagged = tagged(&image.tags);
+        merge_per_file(
+            &mut ours_tagged,
+            &tagged(&base.tags),
+            &tagged(&theirs.tags),
+        );
+        image.tags.clear();
+        for (tag, file) in ours_tagged.into_keys() {
+            image.tags.entry(tag).or_default().insert(file);
+        }
+        image.write_order.merge_from(&theirs.write_order);
+        let order = &image.write_order;
+        limit_per_file(&mut image.scroll_positions, order);
+        limit_per_file(&mut image.rotations, order);
+        limit_per_file(&mut image.folder_rotation, order);
+        limit_per_file(&mut image.antialias_overrides, order);
+        limit_per_file(&mut image.fit_modes, order);
+        if other.updates.last_checked > self.updates.last_checked {
+            self.updates = other.updates.clone();
+        }
+    }
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct EnvVar {
     pub name: String,
     pub value: String,
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Command {
+    #[serde(default)]
     pub input: Vec<String>,
+    /// Events that run the command, see `event_hooks::EVENT_NAMES`. The
+    /// `${event}` placeholder is replaced with the name of the event.
+    pub on_event: Option<Vec<String>>,
     pub program: String,
     pub args: Option<Vec<String>>,
     pub envs: Option<Vec<EnvVar>>,
+    /// Show the next image when the command exits successfully. Only the
+    /// commands run by keys are waited for, so this doesn't apply to events.
+    pub advance_after: Option<bool>,
+}
+
+/// An external command that every displayed still image is passed through.
+///
+/// The decoded image is written to the standard input of `command` as PNG,
+/// and the processed image is read back from its standard output.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct FilterSection {
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub envs: Option<Vec<EnvVar>>,
+}
+
+/// Where the text of a sidecar is shown.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SidecarLocation {
+    /// Over the top of the picture.
+    Overlay,
+    /// In the bottom bar, after the `bottom_bar_fields`.
+    #[default]
+    BottomBar,
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SidecarConfig {
+    /// The extensions of sidecar files, like `["txt", "json"]`. For
+    /// `photo.jpg` both `photo.jpg.txt` and `photo.txt` are looked for.
+    pub extensions: Vec<String>,
+    pub location: Option<SidecarLocation>,
+    /// Larger sidecars are ignored, defaults to 16 KiB.
+    pub max_bytes: Option<u64>,
+}
+
+/// The placeholders that `bottom_bar_fields` may contain.
+pub const BOTTOM_BAR_PLACEHOLDERS: &[&str] = &[
+    "${index}",
+    "${count}",
+    "${width}",
+    "${height}",
+    "${zoom}",
+    "${size}",
+];
+
+/// The digits shown after the decimal point of `${size}` by default.
+pub const DEFAULT_SIZE_PRECISION: u32 = 2;
+
+/// How `${size}` in the bottom bar shows file sizes.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeUnit {
+    /// Powers of 1024, like `1.5 KiB`
+    #[default]
+    Binary,
+    /// Powers of 1000, like `1.54 KB`
+    Decimal,
+}
+impl SizeUnit {
+    /// Formats the number of bytes with at most `precision` digits after the
+    /// decimal point. Trailing zeros are left out.
+    pub fn format(self, bytes: u64, precision: u32) -> String {
+        let (base, units) = match self {
+            SizeUnit::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB"]),
+            SizeUnit::Decimal => (1000.0, ["B", "KB", "MB", "GB", "TB"]),
+        };
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= base && unit < units.len() - 1 {
+            value /= base;
+            unit += 1;
+        }
+        if unit == 0 {
+            return format!("{} {}", bytes, units[0]);
+        }
+        let number = format!("{:.*}", precision as usize, value);
+        let number = if number.contains('.') {
+            number.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &number
+        };
+        format!("{} {}", number, units[unit])
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct TitleSection {
     pub displayed_folders: Option<u32>,
     pub show_program_name: Option<bool>,
@@ -239,7 +1386,7 @@ impl TitleSection {
     }
 }
 
-#[derive(Debug, Default, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Configuration {
     pub bindings: Option<BTreeMap<String, Vec<String>>>,
     pub commands: Option<Vec<Command>>,
@@ -247,16 +1394,793 @@ pub struct Configuration {
     pub title: Option<TitleSection>,
     pub image: Option<ConfigImageSection>,
     pub window: Option<ConfigWindowSection>,
+    pub filter: Option<FilterSection>,
+    pub directory: Option<ConfigDirectorySection>,
+    pub cache: Option<ConfigCacheSection>,
+    pub playback: Option<ConfigPlaybackSection>,
+    pub sidecar: Option<SidecarConfig>,
+    pub logging: Option<ConfigLoggingSection>,
+
+    #[serde(skip)]
+    provenance: BTreeMap<String, Layer>,
 }
 impl Configuration {
-    pub fn load<P: AsRef<Path>>(file_path: P) -> Result<Configuration, String> {
-        let file_path = file_path.as_ref();
-        let cfg_str = fs::read_to_string(file_path).map_err(|_| {
-            format!("Could not read config from {:?}", file_path)
-        })?;
-        let result =
-            toml::from_str(cfg_str.as_ref()).map_err(|e| format!("{}", e))?;
+    /// Combines the config files and the values given on the command line.
+    /// A value from a later layer overrides the same value from all of the
+    /// earlier ones, while the fields it doesn't specify are left intact.
+    ///
+    /// Config files that don't exist are skipped.
+    pub fn load_layers(
+        files: &[(Layer, &Path)],
+        overrides: toml::value::Table,
+    ) -> Result<Configuration, String> {
+        let mut merged = toml::value::Table::new();
+        let mut provenance = BTreeMap::new();
+        for &(layer, file_path) in files {
+            if !file_path.exists() {
+                continue;
+            }
+            let cfg_str = fs::read_to_string(file_path).map_err(|_| {
+                format!("Could not read config from {:?}", file_path)
+            })?;
+            let table: toml::value::Table = toml::from_str(cfg_str.as_ref())
+                .map_err(|e| format!("{:?}: {}", file_path, e))?;
+            merge_layer(&mut merged, table, layer, "", &mut provenance);
+        }
+        merge_layer(
+            &mut merged,
+            overrides,
+            Layer::CommandLine,
+            "",
+            &mut provenance,
+        );
+        let mut result: Configuration = toml::Value::Table(merged)
+            .try_into()
+            .map_err(|e| format!("{}", e))?;
         //println!("Read config from file:\n{:#?}", result);
+        result.provenance = provenance;
         Ok(result)
     }
+
+    /// Returns the layer that supplied each configured value, keyed by the
+    /// dotted path of the value, like `window.min_width`. Fields that aren't
+    /// listed have their default values.
+    pub fn provenance(&self) -> BTreeMap<String, Layer> {
+        self.provenance.clone()
+    }
+
+    /// Returns the minimum inner size of the window as `(width, height)`.
+    ///
+    /// A configured value must be positive and must not exceed the default
+    /// window size, otherwise the default minimum is used.
+    pub fn min_window_size(&self) -> (u32, u32) {
+        let defaults = CacheWindowSection::default();
+        let window = self.window.as_ref();
+        let min_w = match window.and_then(|w| w.min_width) {
+            Some(w) if min_size_valid(w, defaults.win_w) => w,
+            _ => DEFAULT_MIN_WIN_W,
+        };
+        let min_h = match window.and_then(|w| w.min_height) {
+            Some(h) if min_size_valid(h, defaults.win_h) => h,
+            _ => DEFAULT_MIN_WIN_H,
+        };
+        (min_w, min_h)
+    }
+
+    /// Returns a hash of the configured values. Configs that only differ in
+    /// the order of their keys or in formatting have the same hash, and the
+    /// layers that the values come from are ignored. The hash doesn't change
+    /// between runs or builds, so it can be stored to detect changes later.
+    pub fn content_hash(&self) -> u64 {
+        let value = toml::Value::try_from(self).unwrap();
+        let mut hasher = Fnv1a::default();
+        hash_value(&value, &mut hasher);
+        hasher.0
+    }
+
+    /// Runs every validator on the configuration and returns the problems
+    /// found, in the order of `VALIDATORS`.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for validator in VALIDATORS {
+            validator(self, &mut diagnostics);
+        }
+        diagnostics
+    }
+}
+
+/// The 64-bit FNV-1a hash, used by `content_hash` because the hashers of the
+/// standard library may change between releases.
+struct Fnv1a(u64);
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Writes the length first so that adjacent strings can't run together.
+    fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+}
+
+/// Each kind of value starts with a different tag byte, so that for example
+/// the string "1" and the integer 1 hash differently. The keys of tables are
+/// sorted, so their order in the file doesn't matter.
+fn hash_value(value: &toml::Value, hasher: &mut Fnv1a) {
+    match value {
+        toml::Value::String(s) => {
+            hasher.write(&[0]);
+            hasher.write_str(s);
+        }
+        toml::Value::Integer(i) => {
+            hasher.write(&[1]);
+            hasher.write(&i.to_le_bytes());
+        }
+        toml::Value::Float(f) => {
+            hasher.write(&[2]);
+            hasher.write(&f.to_bits().to_le_bytes());
+        }
+        toml::Value::Boolean(b) => hasher.write(&[3, *b as u8]),
+        toml::Value::Datetime(d) => {
+            hasher.write(&[4]);
+            hasher.write_str(&d.to_string());
+        }
+        toml::Value::Array(array) => {
+            hasher.write(&[5]);
+            hasher.write(&(array.len() as u64).to_le_bytes());
+            for item in array {
+                hash_value(item, hasher);
+            }
+        }
+        toml::Value::Table(table) => {
+            hasher.write(&[6]);
+            hasher.write(&(table.len() as u64).to_le_bytes());
+            let mut entries = table.iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, item) in entries {
+                hasher.write_str(key);
+                hash_value(item, hasher);
+            }
+        }
+    }
+}
+
+/// A source of configuration values, in the order of increasing precedence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// The config file shared by every user of the system
+    System,
+    /// The config file of the user
+    User,
+    /// Values set by command line flags
+    CommandLine,
+}
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Layer::System => "system config",
+            Layer::User => "user config",
+            Layer::CommandLine => "command line",
+        })
+    }
+}
+
+/// Recursively merges the tables of `layer` into `target`, recording the
+/// layer of every value that is set. Arrays are replaced as a whole.
+fn merge_layer(
+    target: &mut toml::value::Table,
+    layer: toml::value::Table,
+    layer_id: Layer,
+    prefix: &str,
+    provenance: &mut BTreeMap<String, Layer>,
+) {
+    for (key, value) in layer {
+        let field = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match (target.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge_layer(existing, table, layer_id, &field, provenance);
+            }
+            (_, toml::Value::Table(table)) => {
+                provenance.remove(&field);
+                let mut new_table = toml::value::Table::new();
+                merge_layer(
+                    &mut new_table,
+                    table,
+                    layer_id,
+                    &field,
+                    provenance,
+                );
+                target.insert(key, toml::Value::Table(new_table));
+            }
+            (_, value) => {
+                let sub_prefix = format!("{}.", field);
+                provenance.retain(|k, _| !k.starts_with(&sub_prefix));
+                provenance.insert(field, layer_id);
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+fn min_size_valid(value: u32, default_size: u32) -> bool {
+    value > 0 && value <= default_size
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// The value is used but it's probably not what the user intended.
+    Warning,
+    /// The value can't be used and is ignored.
+    Error,
+}
+
+/// A problem found by `Configuration::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The section and the field the problem is in, like `window.min_width`.
+    pub field: String,
+    pub message: String,
+}
+impl Diagnostic {
+    fn warning<F: Into<String>, M: Into<String>>(field: F, message: M) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    fn error<F: Into<String>, M: Into<String>>(field: F, message: M) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{} in `{}`: {}", severity, self.field, self.message)
+    }
+}
+
+type Validator = fn(&Configuration, &mut Vec<Diagnostic>);
+
+/// Add new checks here to have them run by `Configuration::validate`.
+const VALIDATORS: &[Validator] = &[
+    validate_bindings,
+    validate_commands,
+    validate_image,
+    validate_window,
+    validate_bottom_bar,
+    validate_filter,
+    validate_sidecar,
+    validate_logging,
+    validate_cache,
+    validate_directory,
+    validate_updates,
+];
+
+/// Reports the value of `field` unless it's one of `allowed`.
+fn validate_choice(
+    field: &str,
+    value: Option<&str>,
+    allowed: &[&str],
+    out: &mut Vec<Diagnostic>,
+) {
+    if let Some(value) = value {
+        if !allowed.contains(&value) {
+            out.push(Diagnostic::error(
+                field,
+                format!(
+                    "Illegal value {:?}, allowed values are {:?}",
+                    value, allowed
+                ),
+            ));
+        }
+    }
+}
+
+fn validate_keys(field: &str, keys: &[String], out: &mut Vec<Diagnostic>) {
+    for key in keys {
+        let parts: Vec<_> = key.split('+').map(|s| s.trim()).collect();
+        if parts.last().is_none_or(|k| k.is_empty()) {
+            out.push(Diagnostic::error(
+                field,
+                format!("The binding {:?} doesn't specify a key", key),
+            ));
+        }
+        for modifier in parts.iter().take(parts.len() - 1) {
+            if !MODIFIER_NAMES.contains(&modifier.to_lowercase().as_str()) {
+                out.push(Diagnostic::warning(
+                    field,
+                    format!(
+                        "Unknown modifier {:?} in {:?}, the known ones are {:?}",
+                        modifier, key, MODIFIER_NAMES
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn validate_bindings(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref bindings) = config.bindings {
+        for (action, keys) in bindings.iter() {
+            let field = format!("bindings.{}", action);
+            if !is_action_name(action) {
+                let message = if action == SET_WALLPAPER_NAME {
+                    format!(
+                        "{:?} is only available when built with the `wallpaper` feature",
+                        action
+                    )
+                } else {
+                    format!("There's no action called {:?}", action)
+                };
+                out.push(Diagnostic::warning(&field, message));
+            }
+            validate_keys(&field, keys, out);
+        }
+    }
+}
+
+fn validate_commands(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref commands) = config.commands {
+        for (i, command) in commands.iter().enumerate() {
+            let field = format!("commands[{}]", i);
+            if command.program.is_empty() {
+                out.push(Diagnostic::error(
+                    format!("{}.program", field),
+                    "The program is empty",
+                ));
+            }
+            if command.input.is_empty() && command.on_event.is_none() {
+                out.push(Diagnostic::warning(
+                    format!("{}.input", field),
+                    "No keys or events are bound, the command can't be run",
+                ));
+            }
+            if command.advance_after == Some(true) && command.input.is_empty() {
+                out.push(Diagnostic::warning(
+                    format!("{}.advance_after", field),
+                    "Only commands run by keys advance to the next image",
+                ));
+            }
+            validate_keys(&format!("{}.input", field), &command.input, out);
+            for event in command.on_event.iter().flatten() {
+                validate_choice(
+                    &format!("{}.on_event", field),
+                    Some(event.as_str()),
+                    EVENT_NAMES,
+                    out,
+                );
+            }
+        }
+    }
+}
+
+fn validate_image(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref image) = config.image {
+        validate_choice(
+            "image.antialiasing",
+            image.antialiasing.as_deref(),
+            &["auto", "always", "never", "previous"],
+            out,
+        );
+        validate_choice(
+            "image.wallpaper_mode",
+            image.wallpaper_mode.as_deref(),
+            &["fit", "fill", "stretch", "center"],
+            out,
+        );
+        if image.color_management == Some(true)
+            && !cfg!(feature = "color-management")
+        {
+            out.push(Diagnostic::warning(
+                "image.color_management",
+                "this build doesn't have the `color-management` feature, color profiles are ignored",
+            ));
+        }
+        if image.max_dimension == Some(0) {
+            out.push(Diagnostic::error(
+                "image.max_dimension",
+                "must be greater than 0",
+            ));
+        }
+        if let Some(scale) = image.jpeg_decode_scale {
+            if ![1, 2, 4, 8].contains(&scale) {
+                out.push(Diagnostic::error(
+                    "image.jpeg_decode_scale",
+                    "must be 1, 2, 4 or 8",
+                ));
+            }
+        }
+        match image.thumbnail_command {
+            Some(ref command) if command.is_empty() => {
+                out.push(Diagnostic::error(
+                    "image.thumbnail_command",
+                    "The command is empty",
+                ));
+            }
+            None if image.thumbnail_extensions.is_some() => {
+                out.push(Diagnostic::warning(
+                    "image.thumbnail_extensions",
+                    "There's no thumbnail_command to make the images with",
+                ));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn validate_window(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref window) = config.window {
+        let defaults = CacheWindowSection::default();
+        let min_sizes = [
+            ("window.min_width", window.min_width, defaults.win_w),
+            ("window.min_height", window.min_height, defaults.win_h),
+        ];
+        for (field, value, default_size) in min_sizes {
+            if let Some(value) = value {
+                if !min_size_valid(value, default_size) {
+                    out.push(Diagnostic::error(
+                        field,
+                        format!(
+                            "{} is not between 1 and {}",
+                            value, default_size
+                        ),
+                    ));
+                }
+            }
+        }
+        if let Some(ms) = window.resize_debounce_ms {
+            if ms > MAX_RESIZE_DEBOUNCE_MS {
+                out.push(Diagnostic::error(
+                    "window.resize_debounce_ms",
+                    format!("{} is larger than {}", ms, MAX_RESIZE_DEBOUNCE_MS),
+                ));
+            }
+        }
+        if let Some(ref action) = window.on_close {
+            if !is_action_name(action) {
+                out.push(Diagnostic::error(
+                    "window.on_close",
+                    format!(
+                        "Unknown action {:?}, closing the window quits",
+                        action
+                    ),
+                ));
+            }
+        }
+        for (i, palette) in window.palettes.iter().flatten().enumerate() {
+            let field = format!("window.palettes[{}].name", i);
+            if palette.name.is_empty() {
+                out.push(Diagnostic::error(field, "The name is empty"));
+            } else if window
+                .palettes
+                .iter()
+                .flatten()
+                .take(i)
+                .any(|p| p.name == palette.name)
+            {
+                out.push(Diagnostic::warning(
+                    field,
+                    format!(
+                        "Another palette is also called {:?}, only the first one is remembered",
+                        palette.name
+                    ),
+                ));
+            }
+        }
+        let sizes = [
+            ("window.win_w", window.win_w),
+            ("window.win_h", window.win_h),
+        ];
+        for (field, value) in sizes {
+            if let Some(WindowLength::Percent(percent)) = value {
+                if !percent_valid(percent) {
+                    out.push(Diagnostic::error(
+                        field,
+                        format!("{}% is not between 1% and 100%", percent),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn validate_bottom_bar(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    let window = config.window.as_ref();
+    if let Some(precision) = window.and_then(|w| w.size_precision) {
+        if precision > 6 {
+            out.push(Diagnostic::warning(
+                "window.size_precision",
+                "more than 6 digits after the decimal point aren't useful",
+            ));
+        }
+    }
+    let fields = window.and_then(|w| w.bottom_bar_fields.as_ref());
+    for (i, field) in fields.into_iter().flatten().enumerate() {
+        let mut rest = field.as_str();
+        while let Some(start) = rest.find("${") {
+            let placeholder = match rest[start..].find('}') {
+                Some(len) => &rest[start..=start + len],
+                None => &rest[start..],
+            };
+            if !BOTTOM_BAR_PLACEHOLDERS.contains(&placeholder) {
+                out.push(Diagnostic::warning(
+                    format!("window.bottom_bar_fields[{}]", i),
+                    format!(
+                        "Unknown placeholder {:?}, the known ones are {}",
+                        placeholder,
+                        BOTTOM_BAR_PLACEHOLDERS.join(", ")
+                    ),
+                ));
+            }
+            rest = &rest[start + placeholder.len()..];
+        }
+    }
+}
+
+fn validate_updates(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    let url = config
+        .updates
+        .as_ref()
+        .and_then(|u| u.update_url.as_deref());
+    if let Some(url) = url {
+        let host = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .and_then(|rest| rest.split(&['/', '?', '#'][..]).next());
+        let valid = match host {
+            Some(host) => {
+                !host.is_empty() && !host.contains(char::is_whitespace)
+            }
+            None => false,
+        };
+        if !valid {
+            out.push(Diagnostic::error(
+                "updates.update_url",
+                format!("{:?} is not an http or https URL", url),
+            ));
+        }
+    }
+}
+
+fn validate_filter(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref filter) = config.filter {
+        if filter.command.is_empty() {
+            out.push(Diagnostic::error(
+                "filter.command",
+                "The command is empty",
+            ));
+        }
+    }
+}
+
+fn validate_logging(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref logging) = config.logging {
+        validate_choice(
+            "logging.level",
+            logging.level.as_deref(),
+            &["error", "warn", "info", "debug", "trace"],
+            out,
+        );
+    }
+}
+
+fn validate_cache(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    let backend = config.cache.as_ref().and_then(|c| c.state_backend);
+    if backend == Some(StateBackend::Sqlite) && !cfg!(feature = "sqlite") {
+        out.push(Diagnostic::warning(
+            "cache.state_backend",
+            "this build doesn't have the `sqlite` feature, the state is kept in the cache file",
+        ));
+    }
+}
+
+fn validate_directory(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref directory) = config.directory {
+        if let Some(ref home) = directory.home {
+            if directory.home_path().is_none() {
+                out.push(Diagnostic::warning(
+                    "directory.home",
+                    format!(
+                        "{:?} doesn't exist, an empty window is shown instead",
+                        home
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn validate_sidecar(config: &Configuration, out: &mut Vec<Diagnostic>) {
+    if let Some(ref sidecar) = config.sidecar {
+        if sidecar.extensions.is_empty() {
+            out.push(Diagnostic::warning(
+                "sidecar.extensions",
+                "No extensions are given, so no sidecars are shown",
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// Samples with a name starting with this must fail to load or have at
+    /// least one error.
+    const INVALID_PREFIX: &str = "invalid_";
+
+    fn errors(config: &Configuration) -> Vec<Diagnostic> {
+        config
+            .validate()
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .collect()
+    }
+
+    #[test]
+    fn sample_configs() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/configs");
+        let mut paths: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "toml"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "No sample configs in {:?}", dir);
+
+        for path in paths {
+            let name = path.file_name().unwrap().to_string_lossy();
+            let loaded = Configuration::load_layers(
+                &[(Layer::User, path.as_path())],
+                toml::value::Table::new(),
+            );
+            if name.starts_with(INVALID_PREFIX) {
+                if let Ok(config) = loaded {
+                    assert!(
+                        !errors(&config).is_empty(),
+                        "{} was expected to have errors",
+                        name
+                    );
+                }
+                continue;
+            }
+            let config = loaded.unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(errors(&config), Vec::new(), "{}", name);
+
+            let serialized = toml::to_string(&config)
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let mut reloaded: Configuration = toml::from_str(&serialized)
+                .unwrap_or_else(|e| panic!("{}: {}\n{}", name, e, serialized));
+            reloaded.provenance = config.provenance();
+            assert_eq!(reloaded, config, "{} changed when serialized", name);
+            assert_eq!(
+                reloaded.content_hash(),
+                config.content_hash(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn size_unit_format() {
+        let binary = SizeUnit::Binary;
+        let decimal = SizeUnit::Decimal;
+        assert_eq!(binary.format(0, 2), "0 B");
+        assert_eq!(binary.format(1023, 2), "1023 B");
+        assert_eq!(binary.format(1024, 2), "1 KiB");
+        assert_eq!(binary.format(1536, 2), "1.5 KiB");
+        assert_eq!(binary.format(1536, 0), "2 KiB");
+        assert_eq!(binary.format(3 * 1024 * 1024, 2), "3 MiB");
+        assert_eq!(decimal.format(1536, 2), "1.54 KB");
+        assert_eq!(decimal.format(1536, 1), "1.5 KB");
+        assert_eq!(decimal.format(1_000_000, 3), "1 MB");
+        // Values past the largest unit stay in it
+        assert_eq!(decimal.format(5_000_000_000_000_000, 0), "5000 TB");
+    }
+
+    #[test]
+    fn parse_colors() {
+        let gray = 0x80 as f32 / 255.0;
+        assert_eq!(parse_color("#fff"), Ok([1.0, 1.0, 1.0, 1.0]));
+        assert_eq!(parse_color("#000000"), Ok([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#FF0000"), Ok([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#80808080"), Ok([gray, gray, gray, gray]));
+        assert_eq!(parse_color("#0f0"), parse_color("#00ff00"));
+        for invalid in ["", "#", "fff", "#ff", "#fffff", "#fffffffff", "#ggg"] {
+            assert!(parse_color(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn merge_from_keeps_changes_of_both() {
+        let files = |entries: &[(&str, u32)]| {
+            entries
+                .iter()
+                .map(|&(path, degrees)| (path.to_owned(), degrees))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let mut base = Cache::default();
+        base.image.rotations = files(&[("a", 90), ("b", 90), ("c", 90)]);
+        base.image.tags.insert(
+            "keep".into(),
+            ["a", "b"].iter().map(|&f| f.into()).collect(),
+        );
+        base.loaded_image = base.image.clone();
+
+        let mut ours = base.clone();
+        ours.image.rotations = files(&[("a", 180), ("b", 90), ("c", 90)]);
+        let mut theirs = base.clone();
+        theirs.image.rotations = files(&[("a", 270), ("c", 90), ("d", 90)]);
+        theirs.image.tags.get_mut("keep").unwrap().remove("b");
+        theirs.image.tags.insert("new".into(), ["c".into()].into());
+
+        ours.merge_from(&theirs);
+        assert_eq!(
+            ours.image.rotations,
+            files(&[("a", 180), ("c", 90), ("d", 90)])
+        );
+        assert_eq!(ours.image.tags["keep"], ["a".to_owned()].into());
+        assert_eq!(ours.image.tags["new"], ["c".to_owned()].into());
+    }
+
+    #[test]
+    fn merge_from_limits_the_files() {
+        let mut ours = Cache::default();
+        let mut theirs = Cache::default();
+        for i in 0..MAX_REMEMBERED_FILES {
+            let path = PathBuf::from(format!("/ours/{}", i));
+            ours.image.set_scroll_position(&path, 1);
+            let path = PathBuf::from(format!("/theirs/{}", i));
+            theirs.image.set_scroll_position(&path, 1);
+        }
+        theirs.image.set_scroll_position(Path::new("/theirs/0"), 2);
+        ours.merge_from(&theirs);
+        let positions = &ours.image.scroll_positions;
+        assert_eq!(positions.len(), MAX_REMEMBERED_FILES);
+        assert_eq!(positions.get("/theirs/0"), Some(&2));
+        assert!(!positions.contains_key("/ours/0"));
+    }
+
+    #[test]
+    fn bar_toggle_yields_to_config_changes() {
+        let mut toggle = None;
+        assert!(BarToggle::resolve(&mut toggle, None, true));
+        assert!(!BarToggle::resolve(&mut toggle, Some(false), true));
+
+        let hidden = BarToggle {
+            shown: false,
+            configured: Some(true),
+        };
+        toggle = Some(hidden);
+        assert!(!BarToggle::resolve(&mut toggle, Some(true), true));
+        assert_eq!(toggle, Some(hidden));
+        assert!(BarToggle::resolve(&mut toggle, None, true));
+        assert_eq!(toggle, None);
+    }
 }
@@ -0,0 +1,108 @@
+//! Runs the custom commands that are bound to events with `on_event`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    mem,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    configuration::Configuration, input_handling::execute_event_commands,
+};
+
+pub static IMAGE_OPENED_EVENT: &str = "image_opened";
+pub static IMAGE_CLOSED_EVENT: &str = "image_closed";
+pub static FOLDER_CHANGED_EVENT: &str = "folder_changed";
+
+/// Every event that can be listed in the `on_event` of a command.
+pub static EVENT_NAMES: &[&str] =
+    &[IMAGE_OPENED_EVENT, IMAGE_CLOSED_EVENT, FOLDER_CHANGED_EVENT];
+
+/// The commands of an event are run at most this often. When images are
+/// skipped through faster, only the latest event of each kind is run.
+const MIN_EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+struct PendingEvent {
+    name: &'static str,
+    path: PathBuf,
+}
+
+#[derive(Default)]
+pub struct EventHooks {
+    shown: Option<PathBuf>,
+    pending: Vec<PendingEvent>,
+    last_run: HashMap<&'static str, Instant>,
+}
+
+impl EventHooks {
+    /// Queues the events caused by showing `path` instead of the file shown
+    /// previously, then runs the queued events that are allowed to run.
+    ///
+    /// Returns the time when the next event that's still queued can run.
+    pub fn update(
+        &mut self,
+        config: &Configuration,
+        path: &Option<PathBuf>,
+        tags: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Option<Instant> {
+        if *path != self.shown {
+            let previous = mem::replace(&mut self.shown, path.clone());
+            if has_event_commands(config) {
+                if let Some(ref previous) = previous {
+                    self.queue(IMAGE_CLOSED_EVENT, previous);
+                }
+                if let Some(path) = path {
+                    let previous_folder =
+                        previous.as_ref().and_then(|p| p.parent());
+                    if previous_folder != path.parent() {
+                        self.queue(FOLDER_CHANGED_EVENT, path);
+                    }
+                    self.queue(IMAGE_OPENED_EVENT, path);
+                }
+            }
+        }
+        self.run_pending(config, tags)
+    }
+
+    /// Replaces the event of the same kind if one is already queued.
+    fn queue(&mut self, name: &'static str, path: &Path) {
+        self.pending.retain(|event| event.name != name);
+        self.pending.push(PendingEvent {
+            name,
+            path: path.to_owned(),
+        });
+    }
+
+    fn run_pending(
+        &mut self,
+        config: &Configuration,
+        tags: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Option<Instant> {
+        let now = Instant::now();
+        let mut next_run: Option<Instant> = None;
+        let pending = mem::take(&mut self.pending);
+        for event in pending {
+            let allowed_at = self
+                .last_run
+                .get(event.name)
+                .map_or(now, |&last| last + MIN_EVENT_INTERVAL);
+            if allowed_at <= now {
+                self.last_run.insert(event.name, now);
+                execute_event_commands(config, event.name, &event.path, tags);
+            } else {
+                next_run =
+                    Some(next_run.map_or(allowed_at, |t| t.min(allowed_at)));
+                self.pending.push(event);
+            }
+        }
+        next_run
+    }
+}
+
+fn has_event_commands(config: &Configuration) -> bool {
+    match config.commands {
+        Some(ref commands) => commands.iter().any(|c| c.on_event.is_some()),
+        None => false,
+    }
+}
@@ -0,0 +1,94 @@
+//! Converts images with an embedded ICC profile to sRGB, only available with
+//! the `color-management` feature.
+
+use std::path::Path;
+
+use gelatin::image::RgbaImage;
+
+/// Returns the image converted from its embedded color profile to sRGB.
+///
+/// Images without an embedded profile are returned untouched. If the
+/// conversion fails, a warning is printed and the untransformed image is
+/// returned instead.
+#[cfg(feature = "color-management")]
+pub fn convert_to_srgb(path: &Path, image: RgbaImage) -> RgbaImage {
+    match imp::read_icc_profile(path) {
+        Ok(Some(icc)) => match imp::transform(&icc, &image) {
+            Ok(converted) => converted,
+            Err(e) => {
+                eprintln!(
+                    "Warning: could not apply the color profile of {:?}, showing the image untransformed.\n    {}",
+                    path, e
+                );
+                image
+            }
+        },
+        Ok(None) => image,
+        Err(e) => {
+            eprintln!(
+                "Warning: could not read the color profile of {:?}, showing the image untransformed.\n    {}",
+                path, e
+            );
+            image
+        }
+    }
+}
+
+/// Returns the image untouched without the `color-management` feature.
+#[cfg(not(feature = "color-management"))]
+pub fn convert_to_srgb(_path: &Path, image: RgbaImage) -> RgbaImage {
+    image
+}
+
+#[cfg(feature = "color-management")]
+mod imp {
+    use std::{fs, io::BufReader, path::Path};
+
+    use gelatin::image::{
+        codecs::{jpeg::JpegDecoder, png::PngDecoder},
+        ImageDecoder, ImageFormat, RgbaImage,
+    };
+    use lcms2::{Intent, PixelFormat, Profile, Transform};
+
+    use super::super::image_loader::{detect_format, errors::*, ImgFormat};
+
+    /// Returns the ICC profile embedded in the file, if the format is one
+    /// that the decoders can read profiles from.
+    pub fn read_icc_profile(path: &Path) -> Result<Option<Vec<u8>>> {
+        let format = detect_format(path)?;
+        let reader = BufReader::new(fs::File::open(path)?);
+        let icc = match format {
+            ImgFormat::Image(ImageFormat::Jpeg) => {
+                JpegDecoder::new(reader)?.icc_profile()
+            }
+            ImgFormat::Image(ImageFormat::Png) => {
+                PngDecoder::new(reader)?.icc_profile()
+            }
+            _ => None,
+        };
+        Ok(icc)
+    }
+
+    pub fn transform(icc: &[u8], image: &RgbaImage) -> Result<RgbaImage> {
+        let source = Profile::new_icc(icc).map_err(|e| e.to_string())?;
+        let target = Profile::new_srgb();
+        let transform: Transform<[u8; 4], [u8; 4]> = Transform::new(
+            &source,
+            PixelFormat::RGBA_8,
+            &target,
+            PixelFormat::RGBA_8,
+            Intent::Perceptual,
+        )
+        .map_err(|e| e.to_string())?;
+
+        let (width, height) = image.dimensions();
+        let mut pixels: Vec<[u8; 4]> = image
+            .as_raw()
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect();
+        transform.transform_in_place(&mut pixels);
+        let raw = pixels.into_iter().flatten().collect();
+        Ok(RgbaImage::from_raw(width, height, raw).unwrap())
+    }
+}
@@ -1,13 +1,18 @@
 use std::{
+    cmp::Ordering,
+    collections::HashSet,
     ffi::{OsStr, OsString},
-    fmt, fs,
+    fmt, fs, io, mem,
     path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
 };
 
 use log::debug;
 
 use super::image_loader::is_file_supported;
-use crate::parallel_action::ParallelAction;
+use crate::{configuration::Enumeration, parallel_action::ParallelAction};
 
 #[derive(Debug)]
 pub enum Error {
@@ -33,6 +38,11 @@ impl From<std::io::Error> for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The lazy listing passes on the images it found after this many of them,
+/// or after `LISTING_BATCH_INTERVAL` if that's sooner.
+const LISTING_BATCH_SIZE: usize = 512;
+const LISTING_BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
 macro_rules! step_to_next_img {
     ($this:ident, $iter:ident) => {
         for (i, file) in $iter {
@@ -87,6 +97,235 @@ pub struct Directory {
 
     //filter_state: Arc<Mutex<FilterState>>,
     filter_action: ParallelAction<Vec<DirItem>, Vec<usize>>,
+
+    /// With `Enumeration::Lazy` the directory is listed on a background
+    /// thread, which sends the images in batches as it finds them.
+    lazy: bool,
+
+    /// Receives the batches of the listing in progress. Dropping it stops
+    /// the listing thread.
+    listing: Option<Receiver<ListingMessage>>,
+
+    /// True while the `listing` is in progress. In the meantime `files` holds
+    /// the current file and the images received so far, all of them treated
+    /// as images.
+    enumerating: bool,
+
+    /// The file that was current when the listing started, and whether the
+    /// listing has found it yet.
+    listing_current: Option<(DirItem, bool)>,
+
+    /// Files added to the end of the list with `append`, these are kept
+    /// until the directory is changed.
+    appended: Vec<DirItem>,
+
+    /// When false, hidden files are left out of the listing unless they are
+    /// opened directly.
+    show_hidden: bool,
+
+    follow_symlinks: bool,
+}
+
+enum ListingMessage {
+    /// More of the images in the directory, sorted by their names.
+    Batch(Vec<PathBuf>),
+    /// The listing is complete, or failed.
+    Done(io::Result<()>),
+}
+
+/// Returns true if the entry is a file or a symlink that's listed.
+fn is_listed_entry(entry: &fs::DirEntry, show_hidden: bool) -> bool {
+    match entry.file_type() {
+        Ok(file_type) => {
+            (file_type.is_file() || file_type.is_symlink())
+                && (show_hidden || !is_hidden(&entry.path()))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns true if the file or folder is hidden. On Windows this is the
+/// hidden attribute, elsewhere a name starting with a dot.
+pub fn is_hidden(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        fs::symlink_metadata(path).map_or(false, |metadata| {
+            metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+        })
+    }
+    #[cfg(not(windows))]
+    {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+    }
+}
+
+/// Returns the files and symlinks in the directory sorted by their names.
+/// Hidden files are left out unless `show_hidden` is set.
+fn list_directory(path: &Path, show_hidden: bool) -> io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<_> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_listed_entry(entry, show_hidden))
+        .map(|entry| entry.path())
+        .collect();
+
+    paths.sort_unstable_by(|a, b| compare_file_names(a, b));
+    Ok(paths)
+}
+
+/// Returns the images that opening `dir` yields, in the order they are
+/// navigated.
+///
+/// The files of each directory are sorted by their names, and with
+/// `recursive` they are followed by the images of its subdirectories.
+/// Symlinked directories are only descended into with `follow_symlinks`, and
+/// then each directory is visited once even if several links lead to it.
+/// Hidden files and folders are skipped unless `show_hidden` is set.
+///
+/// The images found through a symlinked directory keep the path of the link,
+/// so their per-file state in the cache is separate from that of the same
+/// images opened through their real path.
+pub fn enumerate(
+    dir: &Path,
+    recursive: bool,
+    show_hidden: bool,
+    follow_symlinks: bool,
+) -> io::Result<Vec<PathBuf>> {
+    let files = list_tree(dir, recursive, show_hidden, follow_symlinks)?;
+    Ok(files
+        .into_iter()
+        .filter(|path| is_file_supported(path))
+        .collect())
+}
+
+/// Returns the files that `enumerate` picks the images from, in the same
+/// order. The eager listing uses this directly and leaves finding the images
+/// to the background filter.
+fn list_tree(
+    dir: &Path,
+    recursive: bool,
+    show_hidden: bool,
+    follow_symlinks: bool,
+) -> io::Result<Vec<PathBuf>> {
+    let mut visited = HashSet::new();
+    if let Ok(canonical) = dir.canonicalize() {
+        visited.insert(canonical);
+    }
+    list_tree_into(dir, recursive, show_hidden, follow_symlinks, &mut visited)
+}
+
+fn list_tree_into(
+    dir: &Path,
+    recursive: bool,
+    show_hidden: bool,
+    follow_symlinks: bool,
+    visited: &mut HashSet<PathBuf>,
+) -> io::Result<Vec<PathBuf>> {
+    let mut files = list_directory(dir, show_hidden)?;
+    if recursive {
+        let mut subdirs: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| match entry.file_type() {
+                Ok(t) if t.is_symlink() => {
+                    follow_symlinks && entry.path().is_dir()
+                }
+                Ok(t) => t.is_dir(),
+                Err(_) => false,
+            })
+            .map(|entry| entry.path())
+            .filter(|path| show_hidden || !is_hidden(path))
+            .collect();
+        subdirs.sort_unstable_by(|a, b| compare_file_names(a, b));
+        for subdir in subdirs {
+            // Breaks the cycles that symlinks may form
+            match subdir.canonicalize() {
+                Ok(canonical) => {
+                    if !visited.insert(canonical) {
+                        continue;
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Could not list the directory {:?}: {}",
+                        subdir, e
+                    );
+                    continue;
+                }
+            }
+            let listed = list_tree_into(
+                &subdir,
+                true,
+                show_hidden,
+                follow_symlinks,
+                visited,
+            );
+            match listed {
+                Ok(sub_files) => files.extend(sub_files),
+                Err(e) => {
+                    eprintln!(
+                        "Could not list the directory {:?}: {}",
+                        subdir, e
+                    )
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Lists the images of the directory, passing them to `send` in sorted
+/// batches as they are found. Stops early when `send` returns false.
+fn list_in_batches(
+    dir: &Path,
+    show_hidden: bool,
+    mut send: impl FnMut(Vec<PathBuf>) -> bool,
+) -> io::Result<()> {
+    let mut batch = Vec::new();
+    let mut batch_started = Instant::now();
+    for entry in fs::read_dir(dir)? {
+        let entry = match entry {
+            Ok(entry) if is_listed_entry(&entry, show_hidden) => entry,
+            _ => continue,
+        };
+        let path = entry.path();
+        if is_file_supported(&path) {
+            batch.push(path);
+        }
+        let full = batch.len() >= LISTING_BATCH_SIZE
+            || batch_started.elapsed() >= LISTING_BATCH_INTERVAL;
+        if full && !batch.is_empty() {
+            batch.sort_unstable_by(|a, b| compare_file_names(a, b));
+            if !send(mem::take(&mut batch)) {
+                return Ok(());
+            }
+            batch_started = Instant::now();
+        }
+    }
+    if !batch.is_empty() {
+        batch.sort_unstable_by(|a, b| compare_file_names(a, b));
+        send(batch);
+    }
+    Ok(())
+}
+
+fn compare_file_names(a: &Path, b: &Path) -> Ordering {
+    lexical_sort::natural_lexical_cmp(
+        &a.file_name().unwrap().to_string_lossy(),
+        &b.file_name().unwrap().to_string_lossy(),
+    )
+}
+
+/// Inserts the item before the first file whose name sorts after it.
+fn insert_by_name(files: &mut Vec<DirItem>, item: DirItem) {
+    let index = files
+        .iter()
+        .position(|other| {
+            compare_file_names(&other.path, &item.path) == Ordering::Greater
+        })
+        .unwrap_or(files.len());
+    files.insert(index, item);
 }
 
 fn get_action() -> impl FnMut(Vec<DirItem>) -> Vec<usize> {
@@ -106,7 +345,11 @@ fn get_action() -> impl FnMut(Vec<DirItem>) -> Vec<usize> {
 }
 
 impl Directory {
-    pub fn new() -> Self {
+    pub fn new(
+        enumeration: Enumeration,
+        show_hidden: bool,
+        follow_symlinks: bool,
+    ) -> Self {
         Directory {
             path: PathBuf::new(),
             files: Vec::new(),
@@ -116,12 +359,40 @@ impl Directory {
             curr_image_idx: 0,
             current_req_id: 0,
             filter_action: ParallelAction::new(get_action()),
+            lazy: enumeration == Enumeration::Lazy,
+            listing: None,
+            enumerating: false,
+            listing_current: None,
+            appended: Vec::new(),
+            show_hidden,
+            follow_symlinks,
         }
     }
 
+    /// Adds the file to the listing if it was only left out because it's
+    /// hidden, so that hidden files can still be opened directly.
+    fn include_hidden(&mut self, file: &Path) {
+        if self.show_hidden
+            || !is_hidden(file)
+            || !file.is_file()
+            || self.files.iter().any(|item| item.path == file)
+        {
+            return;
+        }
+        self.current_req_id += 1;
+        let item = DirItem {
+            path: file.to_owned(),
+            request_id: self.current_req_id,
+        };
+        let mut files = mem::take(&mut self.files);
+        insert_by_name(&mut files, item);
+        self.set_files(files);
+    }
+
     pub fn change_directory(&mut self, path: &Path) -> Result<()> {
         if self.path != path {
             self.path = path.to_owned();
+            self.appended.clear();
             self.collect_directory()
         } else {
             Ok(())
@@ -133,7 +404,31 @@ impl Directory {
         path: &Path,
         filename: &OsStr,
     ) -> Result<()> {
+        if self.lazy && (self.path != path || self.enumerating) {
+            // Show the requested file right away and find its neighbours in
+            // the background.
+            let file = path.join(filename);
+            let current = self.curr_descriptor();
+            if self.path == path
+                && current.is_some_and(|item| item.path == file)
+            {
+                // It's already shown while the listing goes on
+                return Ok(());
+            }
+            if self.path != path {
+                self.path = path.to_owned();
+                self.appended.clear();
+            }
+            self.current_req_id += 1;
+            let item = DirItem {
+                path: file,
+                request_id: self.current_req_id,
+            };
+            self.start_listing(item);
+            return Ok(());
+        }
         self.change_directory(path)?;
+        self.include_hidden(&path.join(filename));
         // Look up the index of the filename in the directory
         for (index, desc) in self.files.iter().enumerate() {
             if desc.path.file_name().unwrap() == filename {
@@ -151,6 +446,110 @@ impl Directory {
         )))
     }
 
+    /// Lists the folder of `file` again without the appended files and makes
+    /// `file` the current one. If `file` doesn't exist anymore, the image
+    /// following its name is selected, or the last image if there's none.
+    /// With the lazy enumeration an existing `file` stays shown while the
+    /// folder is listed in the background.
+    pub fn browse_folder_of(&mut self, file: &Path) -> Result<()> {
+        let folder = file.parent().ok_or_else(|| {
+            Error::Other(format!("{:?} doesn't have a parent folder", file))
+        })?;
+        self.path = folder.to_owned();
+        self.appended.clear();
+        if self.lazy && file.is_file() {
+            self.current_req_id += 1;
+            let item = DirItem {
+                path: file.to_owned(),
+                request_id: self.current_req_id,
+            };
+            self.start_listing(item);
+            return Ok(());
+        }
+        self.collect_directory()?;
+        self.include_hidden(file);
+        let position = self
+            .files
+            .iter()
+            .position(|item| item.path == file)
+            .or_else(|| {
+                self.files.iter().position(|item| {
+                    compare_file_names(&item.path, file) != Ordering::Less
+                        && is_file_supported(&item.path)
+                })
+            })
+            .or_else(|| {
+                self.files
+                    .iter()
+                    .rposition(|item| is_file_supported(&item.path))
+            });
+        if let Some(index) = position {
+            self.curr_file_idx = index;
+            self.set_image_index_from_file_index();
+            self.check_filter_ready();
+        }
+        Ok(())
+    }
+
+    /// Adds the file, or every file of the folder, to the end of the list.
+    /// Files that are already in the list are skipped.
+    pub fn append(&mut self, path: &Path) -> Result<()> {
+        let paths = if path.is_dir() {
+            enumerate(path, false, self.show_hidden, self.follow_symlinks)?
+        } else {
+            vec![path.to_owned()]
+        };
+        for path in paths {
+            if self.files.iter().any(|item| item.path == path) {
+                continue;
+            }
+            self.current_req_id += 1;
+            let item = DirItem {
+                path,
+                request_id: self.current_req_id,
+            };
+            self.appended.push(item.clone());
+            self.files.push(item);
+        }
+        let files = mem::take(&mut self.files);
+        self.set_files(files);
+        Ok(())
+    }
+
+    /// Removes the appended files. Because the current file may be one of
+    /// them, the directory will be listed again when it's next changed to.
+    pub fn clear_appended(&mut self) {
+        if !self.appended.is_empty() {
+            self.appended.clear();
+            self.path = PathBuf::new();
+        }
+    }
+
+    /// Makes the appended file at `path` the current one. Returns false if
+    /// `path` wasn't appended.
+    pub fn select_appended(&mut self, path: &Path) -> bool {
+        if !self.appended.iter().any(|item| item.path == path) {
+            return false;
+        }
+        match self.files.iter().position(|item| item.path == path) {
+            Some(index) => {
+                self.curr_file_idx = index;
+                self.set_image_index_from_file_index();
+                self.check_filter_ready();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn extend_with_appended(&self, files: &mut Vec<DirItem>) {
+        for item in self.appended.iter() {
+            if !files.iter().any(|file| file.path == item.path) {
+                files.push(item.clone());
+            }
+        }
+    }
+
     pub fn curr_filename(&self) -> Option<OsString> {
         self.files
             .get(self.curr_file_idx)
@@ -236,7 +635,28 @@ impl Directory {
         }
     }
 
+    /// Returns true while the directory is being listed in the background. In
+    /// the meantime only the images found so far are available.
+    pub fn enumerating(&mut self) -> bool {
+        self.check_listing_ready();
+        self.enumerating
+    }
+
+    /// Returns true if jumping by `count` images doesn't go past either end
+    /// of the images listed so far.
+    pub fn jump_stays_within(&mut self, count: isize) -> bool {
+        self.check_listing_ready();
+        let target = self.curr_image_idx as isize + count;
+        target >= 0 && (target as usize) < self.img_i_to_file_i.len()
+    }
+
     pub fn update_directory(&mut self) -> Result<()> {
+        if self.lazy {
+            if let Some(item) = self.curr_descriptor().cloned() {
+                self.start_listing(item);
+                return Ok(());
+            }
+        }
         let curr_filename = self.curr_filename();
         let curr_filename = curr_filename.as_deref();
         let curr_index = self.curr_file_idx;
@@ -245,6 +665,9 @@ impl Directory {
 			curr_filename, curr_index
 		);
         self.collect_directory()?;
+        if let Some(curr_filename) = curr_filename {
+            self.include_hidden(&self.path.join(curr_filename));
+        }
         if curr_filename.is_some() {
             for (index, desc) in self.files.iter().enumerate() {
                 if desc.path.file_name() == curr_filename {
@@ -280,32 +703,25 @@ impl Directory {
     }
 
     pub fn collect_directory(&mut self) -> Result<()> {
-        let mut dir_files: Vec<_> = fs::read_dir(&self.path)?
-            .filter_map(|x| match x {
-                Ok(entry) => match entry.file_type() {
-                    Ok(file_type) => {
-                        if file_type.is_file() || file_type.is_symlink() {
-                            self.current_req_id += 1;
-                            Some(DirItem {
-                                path: entry.path(),
-                                request_id: self.current_req_id,
-                            })
-                        } else {
-                            None
-                        }
-                    }
-                    Err(_) => None,
-                },
-                Err(_) => None,
-            })
-            .collect();
-
-        dir_files.sort_unstable_by(|a, b| {
-            lexical_sort::natural_lexical_cmp(
-                &a.path.file_name().unwrap().to_string_lossy(),
-                &b.path.file_name().unwrap().to_string_lossy(),
-            )
-        });
+        self.enumerating = false;
+        self.listing = None;
+        self.listing_current = None;
+        let mut dir_files: Vec<_> = list_tree(
+            &self.path,
+            false,
+            self.show_hidden,
+            self.follow_symlinks,
+        )?
+        .into_iter()
+        .map(|path| {
+            self.current_req_id += 1;
+            DirItem {
+                path,
+                request_id: self.current_req_id,
+            }
+        })
+        .collect();
+        self.extend_with_appended(&mut dir_files);
 
         // Set the current file index to the first image
         for (i, item) in dir_files.iter().enumerate() {
@@ -314,11 +730,166 @@ impl Directory {
                 break;
             }
         }
-        self.filter_action.give_input(dir_files.clone());
+        self.set_files(dir_files);
+        Ok(())
+    }
+
+    fn set_files(&mut self, files: Vec<DirItem>) {
+        self.filter_action.give_input(files.clone());
         self.img_i_to_file_i.clear();
         self.file_i_to_img_i.clear();
-        self.files = dir_files;
-        Ok(())
+        self.files = files;
+    }
+
+    /// Makes `current` the only known file and starts listing the directory
+    /// in the background. A listing that's still in progress is abandoned.
+    fn start_listing(&mut self, current: DirItem) {
+        let (sender, receiver) = mpsc::channel();
+        let path = self.path.clone();
+        let show_hidden = self.show_hidden;
+        thread::spawn(move || {
+            let result = list_in_batches(&path, show_hidden, |batch| {
+                sender.send(ListingMessage::Batch(batch)).is_ok()
+            });
+            let _ = sender.send(ListingMessage::Done(result));
+        });
+        self.listing = Some(receiver);
+        self.enumerating = true;
+        self.listing_current = Some((current.clone(), false));
+        self.files = vec![current];
+        self.curr_file_idx = 0;
+        self.curr_image_idx = 0;
+        self.set_listed_so_far();
+    }
+
+    /// Takes the batches that arrived from the listing thread.
+    fn check_listing_ready(&mut self) {
+        if !self.enumerating {
+            return;
+        }
+        let mut received = false;
+        loop {
+            let message = match self.listing {
+                Some(ref listing) => listing.try_recv(),
+                None => Err(TryRecvError::Disconnected),
+            };
+            match message {
+                Ok(ListingMessage::Batch(paths)) => {
+                    self.merge_listed(paths);
+                    received = true;
+                }
+                Ok(ListingMessage::Done(result)) => {
+                    if let Err(e) = result {
+                        eprintln!(
+                            "Could not list the directory {:?}: {}",
+                            self.path, e
+                        );
+                    }
+                    self.finish_listing();
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finish_listing();
+                    return;
+                }
+            }
+        }
+        if received {
+            self.set_listed_so_far();
+        }
+    }
+
+    /// Inserts the sorted `paths` among the files, keeping the current file
+    /// current.
+    fn merge_listed(&mut self, paths: Vec<PathBuf>) {
+        let current_path = self.curr_descriptor().map(|item| item.path.clone());
+        let mut next_req_id = self.current_req_id;
+        let mut merged = Vec::with_capacity(self.files.len() + paths.len());
+        let mut files = mem::take(&mut self.files).into_iter().peekable();
+        for path in paths {
+            if let Some((ref current, ref mut found)) = self.listing_current {
+                if current.path == path {
+                    // The current file is already in the list
+                    *found = true;
+                    continue;
+                }
+            }
+            while let Some(file) = files.next_if(|file| {
+                compare_file_names(&file.path, &path) != Ordering::Greater
+            }) {
+                merged.push(file);
+            }
+            next_req_id += 1;
+            merged.push(DirItem {
+                path,
+                request_id: next_req_id,
+            });
+        }
+        merged.extend(files);
+        self.current_req_id = next_req_id;
+        self.files = merged;
+        if let Some(current_path) = current_path {
+            if let Some(idx) =
+                self.files.iter().position(|item| item.path == current_path)
+            {
+                self.curr_file_idx = idx;
+            }
+        }
+    }
+
+    /// While listing, every file is taken to be an image, as the listing
+    /// thread only sends images.
+    fn set_listed_so_far(&mut self) {
+        self.img_i_to_file_i = (0..self.files.len()).collect();
+        self.finished_filtering();
+    }
+
+    fn finish_listing(&mut self) {
+        self.enumerating = false;
+        self.listing = None;
+        let mut dir_files = mem::take(&mut self.files);
+        let current = dir_files.get(self.curr_file_idx).cloned();
+        if let Some((listed, false)) = self.listing_current.take() {
+            let kept = !self.show_hidden
+                && is_hidden(&listed.path)
+                && listed.path.is_file();
+            if !kept {
+                // The file that was opened isn't in the folder anymore
+                dir_files.retain(|item| item.path != listed.path);
+            }
+        }
+        self.extend_with_appended(&mut dir_files);
+        let current_idx = current.as_ref().and_then(|current| {
+            dir_files.iter().position(|item| item.path == current.path)
+        });
+
+        self.curr_file_idx = match (current_idx, current) {
+            (Some(idx), _) => idx,
+            (None, current) => {
+                // The current file disappeared, so go to the first image
+                // following its place.
+                let start = match current {
+                    Some(current) => dir_files
+                        .iter()
+                        .position(|item| {
+                            compare_file_names(&item.path, &current.path)
+                                != Ordering::Less
+                        })
+                        .unwrap_or(0),
+                    None => 0,
+                };
+                dir_files
+                    .iter()
+                    .enumerate()
+                    .skip(start)
+                    .chain(dir_files.iter().enumerate().take(start))
+                    .find(|(_, item)| is_file_supported(&item.path))
+                    .map(|(i, _)| i)
+                    .unwrap_or(0)
+            }
+        };
+        self.set_files(dir_files);
     }
 
     fn finished_filtering(&mut self) {
@@ -344,6 +915,11 @@ impl Directory {
     }
 
     fn check_filter_ready(&mut self) -> bool {
+        self.check_listing_ready();
+        if self.enumerating {
+            // The images listed so far are already known
+            return true;
+        }
         if let Some(out) = self.filter_action.try_get_output() {
             self.img_i_to_file_i = out;
             self.finished_filtering();
@@ -352,3 +928,104 @@ impl Directory {
         self.filter_action.is_ready()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::*;
+
+    /// Creates an empty folder in the temporary folder with the given files,
+    /// which are all empty. Names ending with a slash are made folders.
+    fn make_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "alloy-directory-{}-{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            if file.ends_with('/') {
+                fs::create_dir_all(dir.join(file)).unwrap();
+            } else {
+                fs::write(dir.join(file), b"").unwrap();
+            }
+        }
+        dir
+    }
+
+    fn names(dir: &Path, paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| {
+                p.strip_prefix(dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enumerate_filters_and_sorts() {
+        let dir = make_dir(
+            "filters",
+            &["10.png", "2.jpg", "notes.txt", "1.gif", "sub/", "sub/3.png"],
+        );
+        let images = enumerate(&dir, false, false, false).unwrap();
+        assert_eq!(names(&dir, &images), ["1.gif", "2.jpg", "10.png"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn enumerate_hidden_files() {
+        let dir =
+            make_dir("hidden", &["a.png", ".b.png", ".sub/", ".sub/c.png"]);
+        let images = enumerate(&dir, true, false, false).unwrap();
+        assert_eq!(names(&dir, &images), ["a.png"]);
+        let images = enumerate(&dir, true, true, false).unwrap();
+        assert_eq!(names(&dir, &images), [".b.png", "a.png", ".sub/c.png"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn enumerate_recursive() {
+        let dir = make_dir(
+            "recursive",
+            &[
+                "b.png",
+                "a/",
+                "a/2.png",
+                "a/1.png",
+                "a/x/",
+                "a/x/y.png",
+                "c/",
+            ],
+        );
+        let images = enumerate(&dir, true, false, false).unwrap();
+        assert_eq!(
+            names(&dir, &images),
+            ["b.png", "a/1.png", "a/2.png", "a/x/y.png"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn enumerate_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = make_dir("symlinks", &["a.png", "sub/", "sub/b.png"]);
+        symlink(dir.join("a.png"), dir.join("c.png")).unwrap();
+        symlink(dir.join("sub"), dir.join("link")).unwrap();
+        let images = enumerate(&dir, false, false, false).unwrap();
+        assert_eq!(names(&dir, &images), ["a.png", "c.png"]);
+        let images = enumerate(&dir, true, false, false).unwrap();
+        assert_eq!(names(&dir, &images), ["a.png", "c.png", "sub/b.png"]);
+        // The folder is visited once, through the link that sorts first
+        let images = enumerate(&dir, true, false, true).unwrap();
+        assert_eq!(names(&dir, &images), ["a.png", "c.png", "link/b.png"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
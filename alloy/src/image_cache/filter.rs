@@ -0,0 +1,124 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    io::{Cursor, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::Mutex,
+    thread,
+};
+
+use gelatin::image::{self, ImageOutputFormat, RgbaImage};
+
+use super::image_loader::errors::*;
+use crate::{
+    configuration::FilterSection, input_handling::substitute_command_parameters,
+};
+
+/// The number of filtered images kept around so that revisiting an image
+/// doesn't require running the filter again.
+const MAX_CACHED_RESULTS: usize = 8;
+
+#[derive(Default)]
+struct FilterCache {
+    /// Input hashes in the order they were inserted, oldest first.
+    order: VecDeque<u64>,
+    results: HashMap<u64, RgbaImage>,
+}
+
+/// Passes decoded images through the external command specified in the
+/// `[filter]` section of the config.
+///
+/// The image is written to the standard input of the command as PNG and the
+/// command is expected to write the processed image to its standard output
+/// in any of the supported formats.
+pub struct ImageFilter {
+    config: FilterSection,
+    cache: Mutex<FilterCache>,
+}
+
+impl ImageFilter {
+    pub fn new(config: FilterSection) -> ImageFilter {
+        ImageFilter {
+            config,
+            cache: Mutex::new(FilterCache::default()),
+        }
+    }
+
+    /// Returns the filtered image. If the filter fails for any reason, a
+    /// warning is printed and the original image is returned instead.
+    pub fn apply(&self, path: &Path, image: RgbaImage) -> RgbaImage {
+        let hash = input_hash(&image);
+        if let Some(cached) = self.cache.lock().unwrap().results.get(&hash) {
+            return cached.clone();
+        }
+        match self.run(path, &image) {
+            Ok(filtered) => {
+                let mut cache = self.cache.lock().unwrap();
+                if cache.order.len() >= MAX_CACHED_RESULTS {
+                    if let Some(oldest) = cache.order.pop_front() {
+                        cache.results.remove(&oldest);
+                    }
+                }
+                cache.order.push_back(hash);
+                cache.results.insert(hash, filtered.clone());
+                filtered
+            }
+            Err(e) => {
+                eprintln!(
+                    "Warning: the image filter failed for {:?}, showing the unfiltered image.\n    {}",
+                    path, e
+                );
+                image
+            }
+        }
+    }
+
+    fn run(&self, path: &Path, image: &RgbaImage) -> Result<RgbaImage> {
+        let mut input = Vec::new();
+        image.write_to(&mut Cursor::new(&mut input), ImageOutputFormat::Png)?;
+
+        let mut var_map = HashMap::with_capacity(1);
+        let img_path = path.to_string_lossy();
+        var_map.insert("${img}", img_path.as_ref());
+
+        let mut cmd = Command::new(&self.config.command);
+        if let Some(ref args) = self.config.args {
+            cmd.args(
+                args.iter()
+                    .map(|arg| substitute_command_parameters(arg, &var_map)),
+            );
+        }
+        if let Some(ref envs) = self.config.envs {
+            cmd.envs(envs.iter().map(|env_var| {
+                (env_var.name.as_str(), env_var.value.as_str())
+            }));
+        }
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        let mut child = cmd.spawn()?;
+
+        // Feed the input from a separate thread, otherwise a filter that
+        // starts writing its output before consuming all of its input could
+        // deadlock with us.
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        // A filter is allowed to close its input early, so only its exit
+        // status and its output decide whether it succeeded.
+        let _ = writer.join();
+
+        if !output.status.success() {
+            bail!("The filter command exited with {}", output.status);
+        }
+        Ok(image::load_from_memory(&output.stdout)?.into_rgba8())
+    }
+}
+
+fn input_hash(image: &RgbaImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.dimensions().hash(&mut hasher);
+    image.as_raw().hash(&mut hasher);
+    hasher.finish()
+}
@@ -12,8 +12,14 @@ use std::{
 
 use gelatin::image::{
     self,
-    codecs::{gif::GifDecoder, png::PngDecoder},
-    AnimationDecoder, ImageFormat,
+    codecs::{gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder},
+    AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat,
+};
+
+use super::{
+    color,
+    filter::ImageFilter,
+    thumbnailer::{has_thumbnail_extension, Thumbnailer},
 };
 
 pub mod errors {
@@ -55,7 +61,7 @@ pub enum ImgFormat {
 /// image. This is represented by the value `Deg0`. All other cases must be interpreted as relative
 /// to this. The rotation part is counter-clockwise. When there's a flip it's always interpreted as
 /// if it happened after the rotation.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Orientation {
     /// Exif 1
     Deg0,
@@ -86,6 +92,42 @@ impl Default for Orientation {
         Orientation::Deg0
     }
 }
+impl Orientation {
+    /// Returns the orientation that shows the image turned clockwise by
+    /// `quarter_turns` * 90 degrees compared to this one.
+    pub fn rotated_cw(self, quarter_turns: u32) -> Orientation {
+        use Orientation::*;
+        // Counter-clockwise quarter turns followed by an optional horizontal
+        // flip. A vertical flip is a horizontal flip after a half turn.
+        let (ccw_turns, flip) = match self {
+            Deg0 => (0, false),
+            Deg90 => (1, false),
+            Deg180 => (2, false),
+            Deg270 => (3, false),
+            Deg0HorFlip => (0, true),
+            Deg270VerFlip => (1, true),
+            Deg180HorFlip => (2, true),
+            Deg90VerFlip => (3, true),
+        };
+        // Turning a flipped image clockwise equals turning it the other way
+        // before the flip.
+        let ccw_turns = if flip {
+            (ccw_turns + quarter_turns) % 4
+        } else {
+            (ccw_turns + 4 - quarter_turns % 4) % 4
+        };
+        match (ccw_turns, flip) {
+            (0, false) => Deg0,
+            (1, false) => Deg90,
+            (2, false) => Deg180,
+            (3, false) => Deg270,
+            (0, true) => Deg0HorFlip,
+            (1, true) => Deg270VerFlip,
+            (2, true) => Deg180HorFlip,
+            _ => Deg90VerFlip,
+        }
+    }
+}
 
 /// Detects the format of an image file. It looks at the first 512 bytes;
 /// if that fails, it uses the file ending.
@@ -157,6 +199,29 @@ pub fn simple_load_image(
     Ok(image::load(reader, image_format)?.into_rgba8())
 }
 
+/// Decodes the JPEG file at `1 / scale` of its size, or smaller if needed to
+/// get close to `max_dimension`, which the decoder can do without decoding
+/// the full image first. The result may still be larger than `max_dimension`.
+fn load_scaled_jpeg(
+    path: &Path,
+    scale: u8,
+    max_dimension: Option<u32>,
+) -> Result<image::RgbaImage> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut decoder = JpegDecoder::new(reader)?;
+    let (w, h) = decoder.dimensions();
+    let scale = scale as u32;
+    let mut requested_w = w.div_ceil(scale);
+    let mut requested_h = h.div_ceil(scale);
+    if let Some(max) = max_dimension.filter(|&max| w.max(h) > max) {
+        let ratio = max as f64 / w.max(h) as f64;
+        requested_w = requested_w.min((w as f64 * ratio).ceil() as u32);
+        requested_h = requested_h.min((h as f64 * ratio).ceil() as u32);
+    }
+    decoder.scale(requested_w as u16, requested_h as u16)?;
+    Ok(DynamicImage::from_decoder(decoder)?.into_rgba8())
+}
+
 /// Returns an iterator over the animation frames of a GIF file
 pub fn load_gif(
     path: &Path,
@@ -193,6 +258,32 @@ pub fn complex_load_image<F>(
     path: &Path,
     allow_animation: bool,
     req_id: u32,
+    process_image: F,
+) -> Result<()>
+where
+    F: FnMut(LoadResult) -> Result<()>,
+{
+    complex_load_scaled_image(
+        path,
+        allow_animation,
+        1,
+        None,
+        req_id,
+        process_image,
+    )
+}
+
+/// Like `complex_load_image`, but JPEG images are decoded at
+/// `1 / jpeg_scale` of their size, which is much faster for large files.
+/// JPEG images larger than `max_dimension` are also decoded at a smaller
+/// size, without decoding them fully first. Other formats are decoded fully
+/// and left for the caller to downscale.
+pub fn complex_load_scaled_image<F>(
+    path: &Path,
+    allow_animation: bool,
+    jpeg_scale: u8,
+    max_dimension: Option<u32>,
+    req_id: u32,
     mut process_image: F,
 ) -> Result<()>
 where
@@ -234,6 +325,17 @@ where
                 })?;
             }
         }
+        ImgFormat::Image(ImageFormat::Jpeg)
+            if jpeg_scale > 1 || max_dimension.is_some() =>
+        {
+            let image = load_scaled_jpeg(path, jpeg_scale, max_dimension)?;
+            process_image(LoadResult::Frame {
+                req_id,
+                image,
+                delay_nano: 0,
+                orientation,
+            })?;
+        }
         ImgFormat::Image(image_format) => {
             let image = simple_load_image(path, image_format)?;
             process_image(LoadResult::Frame {
@@ -312,19 +414,61 @@ pub fn is_file_supported(filename: &Path) -> bool {
             }
         }
     }
-    detect_format(filename).is_ok()
+    has_thumbnail_extension(filename) || detect_format(filename).is_ok()
+}
+
+/// Settings that affect how the loader threads decode images.
+pub struct DecodeSettings {
+    /// The external command that still images are passed through after
+    /// decoding. Animated images are shown unfiltered.
+    pub filter: Option<ImageFilter>,
+    /// When false, the EXIF orientation of images is ignored.
+    pub auto_orient: bool,
+    /// Convert still images with an embedded color profile to sRGB.
+    pub color_management: bool,
+    /// Images larger than this in either dimension are downscaled to fit,
+    /// unless the request asks for the full resolution.
+    pub max_dimension: Option<u32>,
+    /// JPEG images are decoded at `1 / jpeg_decode_scale` of their size,
+    /// unless the request asks for the full quality. 1 decodes them fully.
+    pub jpeg_decode_scale: u8,
+    /// Makes the images of the files that can't be decoded.
+    pub thumbnailer: Option<Thumbnailer>,
+}
+impl Default for DecodeSettings {
+    fn default() -> Self {
+        DecodeSettings {
+            filter: None,
+            auto_orient: true,
+            color_management: false,
+            max_dimension: None,
+            jpeg_decode_scale: 1,
+            thumbnailer: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct LoadRequest {
     pub req_id: u32,
     pub path: PathBuf,
+    /// Ignore `DecodeSettings::max_dimension` for this image
+    pub full_resolution: bool,
+    /// Ignore `DecodeSettings::jpeg_decode_scale` for this image
+    pub full_quality: bool,
 }
 
 pub enum LoadResult {
     Start {
         req_id: u32,
         metadata: fs::Metadata,
+        /// The frames are downscaled to fit within `max_dimension`
+        downscaled: bool,
+        /// The image is decoded at a smaller size by `jpeg_decode_scale`
+        reduced_quality: bool,
+        /// The size of the image in the file, if the frames are decoded at a
+        /// smaller size. The frames are shown at this size.
+        original_size: Option<(u32, u32)>,
     },
     Frame {
         req_id: u32,
@@ -334,6 +478,11 @@ pub enum LoadResult {
         /// How much does the image need to be rotated counter-clockwise to be shown correctly
         orientation: Orientation,
     },
+    /// Sent after the first frame, counted from the image that's shown
+    Levels {
+        req_id: u32,
+        levels: Box<Levels>,
+    },
     Done {
         req_id: u32,
     },
@@ -347,12 +496,44 @@ impl LoadResult {
         match self {
             LoadResult::Start { req_id, .. } => *req_id,
             LoadResult::Frame { req_id, .. } => *req_id,
+            LoadResult::Levels { req_id, .. } => *req_id,
             LoadResult::Done { req_id, .. } => *req_id,
             LoadResult::Failed { req_id, .. } => *req_id,
         }
     }
 }
 
+/// Larger images are sampled at every few pixels to stay fast.
+const MAX_SAMPLED_PIXELS: usize = 1 << 20;
+
+/// The number of pixels at each of the 256 levels of red, green, blue and
+/// luminance, which the histogram is drawn from.
+pub struct Levels {
+    pub channels: [[u32; 256]; 3],
+    pub luminance: [u32; 256],
+}
+
+impl Levels {
+    pub fn count(image: &image::RgbaImage) -> Levels {
+        let mut levels = Levels {
+            channels: [[0; 256]; 3],
+            luminance: [0; 256],
+        };
+        let pixel_count = image.width() as usize * image.height() as usize;
+        let step = (pixel_count / MAX_SAMPLED_PIXELS).max(1);
+        for pixel in image.as_raw().chunks_exact(4).step_by(step) {
+            let (r, g, b) = (pixel[0], pixel[1], pixel[2]);
+            levels.channels[0][r as usize] += 1;
+            levels.channels[1][g as usize] += 1;
+            levels.channels[2][b as usize] += 1;
+            let luminance =
+                (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+            levels.luminance[luminance as usize] += 1;
+        }
+        levels
+    }
+}
+
 pub struct ImageLoader {
     running: Arc<AtomicBool>,
     join_handles: Option<Vec<thread::JoinHandle<()>>>,
@@ -363,8 +544,9 @@ pub struct ImageLoader {
 impl ImageLoader {
     /// # Arguemnts
     /// * `capacity` - Number of bytes. The last image loaded will be the one at which the allocated memory reaches or exceeds capacity
-    pub fn new(threads: u32) -> ImageLoader {
+    pub fn new(threads: u32, settings: DecodeSettings) -> ImageLoader {
         let running = Arc::new(AtomicBool::from(true));
+        let settings = Arc::new(settings);
         let (load_request_tx, load_request_rx) = channel();
         let load_request_rx = Arc::new(Mutex::new(load_request_rx));
 
@@ -376,12 +558,14 @@ impl ImageLoader {
             let request_recv = load_request_rx.clone();
             let request_send = load_request_tx.clone();
             let img_sender = loaded_img_tx.clone();
+            let settings = settings.clone();
             join_handles.push(thread::spawn(move || {
                 Self::thread_loop(
                     running,
                     request_recv,
                     request_send,
                     img_sender,
+                    settings,
                 );
             }));
         }
@@ -400,6 +584,7 @@ impl ImageLoader {
         request_recv: Arc<Mutex<Receiver<LoadRequest>>>,
         request_send: Sender<LoadRequest>,
         img_sender: Sender<LoadResult>,
+        settings: Arc<DecodeSettings>,
     ) {
         // The size was an arbitrary choice made with the argument that this should be
         // enough to fit enough image file info to determine the format.
@@ -423,7 +608,7 @@ impl ImageLoader {
                     continue;
                 }
             };
-            Self::load_and_send(&img_sender, request);
+            Self::load_and_send(&img_sender, request, &settings);
         }
     }
 
@@ -437,27 +622,129 @@ impl ImageLoader {
         self.path_tx.send(request).unwrap();
     }
 
-    fn load_and_send(img_sender: &Sender<LoadResult>, request: LoadRequest) {
+    fn load_and_send(
+        img_sender: &Sender<LoadResult>,
+        request: LoadRequest,
+        settings: &DecodeSettings,
+    ) {
         fn try_load_and_send(
             img_sender: &Sender<LoadResult>,
             request: &LoadRequest,
+            settings: &DecodeSettings,
         ) -> Result<()> {
             let metadata = fs::metadata(&request.path)?;
+            let is_jpeg = matches!(
+                detect_format(&request.path),
+                Ok(ImgFormat::Image(ImageFormat::Jpeg))
+            );
+            // Only the header is read here. Formats that `image` can't read
+            // the size of are never downscaled.
+            let dimensions = image::image_dimensions(&request.path).ok();
+            let max_dimension = settings
+                .max_dimension
+                .filter(|_| !request.full_resolution)
+                .filter(|&max| match dimensions {
+                    Some((w, h)) => w.max(h) > max,
+                    None => false,
+                });
+            let jpeg_scale = match settings.jpeg_decode_scale {
+                scale if scale > 1 && !request.full_quality && is_jpeg => scale,
+                _ => 1,
+            };
+            let original_size = dimensions
+                .filter(|_| max_dimension.is_some() || jpeg_scale > 1);
             img_sender
                 .send(LoadResult::Start {
                     req_id: request.req_id,
                     metadata,
+                    downscaled: max_dimension.is_some(),
+                    reduced_quality: jpeg_scale > 1,
+                    original_size,
                 })
                 .unwrap();
-            complex_load_image(&request.path, true, request.req_id, |frame| {
+            let mut levels_sent = false;
+            let mut process_frame = |frame: LoadResult| {
+                let frame = match frame {
+                    LoadResult::Frame {
+                        req_id,
+                        mut image,
+                        delay_nano,
+                        mut orientation,
+                    } => {
+                        if !settings.auto_orient {
+                            orientation = Orientation::Deg0;
+                        }
+                        if let Some(max) = max_dimension {
+                            image = downscale(image, max);
+                        }
+                        if delay_nano == 0 && settings.color_management {
+                            image =
+                                color::convert_to_srgb(&request.path, image);
+                        }
+                        if let (0, Some(filter)) =
+                            (delay_nano, &settings.filter)
+                        {
+                            image = filter.apply(&request.path, image);
+                        }
+                        LoadResult::Frame {
+                            req_id,
+                            image,
+                            delay_nano,
+                            orientation,
+                        }
+                    }
+                    frame => frame,
+                };
+                // Counted here so that the histogram never has to read the
+                // textures back from the GPU
+                let levels = match &frame {
+                    LoadResult::Frame { image, .. } if !levels_sent => {
+                        levels_sent = true;
+                        Some(Box::new(Levels::count(image)))
+                    }
+                    _ => None,
+                };
                 img_sender.send(frame).unwrap();
+                if let Some(levels) = levels {
+                    img_sender
+                        .send(LoadResult::Levels {
+                            req_id: request.req_id,
+                            levels,
+                        })
+                        .unwrap();
+                }
                 Ok(())
-            })?;
-            Ok(())
+            };
+            let decoded = match settings.thumbnailer {
+                // These are never decoded, the error only selects the
+                // thumbnailer below
+                Some(_) if has_thumbnail_extension(&request.path) => {
+                    Err("The file has one of the thumbnail_extensions".into())
+                }
+                _ => complex_load_scaled_image(
+                    &request.path,
+                    true,
+                    jpeg_scale,
+                    max_dimension,
+                    request.req_id,
+                    &mut process_frame,
+                ),
+            };
+            match (decoded, &settings.thumbnailer) {
+                (Err(_), Some(thumbnailer)) => {
+                    process_frame(LoadResult::Frame {
+                        req_id: request.req_id,
+                        image: thumbnailer.load(&request.path),
+                        delay_nano: 0,
+                        orientation: Orientation::Deg0,
+                    })
+                }
+                (decoded, _) => decoded,
+            }
         }
 
         img_sender
-            .send(match try_load_and_send(img_sender, &request) {
+            .send(match try_load_and_send(img_sender, &request, settings) {
                 Ok(()) => LoadResult::Done {
                     req_id: request.req_id,
                 },
@@ -475,6 +762,24 @@ impl ImageLoader {
     }
 }
 
+/// Returns the image resized to fit within `max` pixels in both dimensions,
+/// keeping the aspect ratio.
+fn downscale(image: image::RgbaImage, max: u32) -> image::RgbaImage {
+    let (w, h) = image.dimensions();
+    if w.max(h) <= max {
+        return image;
+    }
+    let scale = max as f64 / w.max(h) as f64;
+    let new_w = ((w as f64 * scale).round() as u32).clamp(1, max);
+    let new_h = ((h as f64 * scale).round() as u32).clamp(1, max);
+    image::imageops::resize(
+        &image,
+        new_w,
+        new_h,
+        image::imageops::FilterType::Triangle,
+    )
+}
+
 impl Drop for ImageLoader {
     fn drop(&mut self) {
         self.running.store(false, Ordering::Release);
@@ -484,6 +789,8 @@ impl Drop for ImageLoader {
                     .send(LoadRequest {
                         req_id: 0,
                         path: PathBuf::from(""),
+                        full_resolution: false,
+                        full_quality: false,
                     })
                     .unwrap();
             }
@@ -496,3 +803,163 @@ impl Drop for ImageLoader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    const ALL_ORIENTATIONS: [Orientation; 8] = [
+        Orientation::Deg0,
+        Orientation::Deg0HorFlip,
+        Orientation::Deg180,
+        Orientation::Deg180HorFlip,
+        Orientation::Deg90VerFlip,
+        Orientation::Deg270,
+        Orientation::Deg270VerFlip,
+        Orientation::Deg90,
+    ];
+
+    /// A JPEG that only has an EXIF segment with the orientation tag.
+    fn jpeg_with_orientation(value: u16) -> Vec<u8> {
+        let mut tiff = vec![b'I', b'I', 0x2a, 0, 8, 0, 0, 0, 1, 0];
+        // The orientation tag, a single short
+        tiff.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0]);
+        tiff.extend_from_slice(&value.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xff, 0xd9]);
+        jpeg
+    }
+
+    type Grid = Vec<Vec<u8>>;
+
+    fn turn_ccw(grid: &Grid) -> Grid {
+        let cols = grid[0].len();
+        (0..cols)
+            .map(|i| grid.iter().map(|row| row[cols - 1 - i]).collect())
+            .collect()
+    }
+
+    fn turn_cw(grid: &Grid) -> Grid {
+        turn_ccw(&turn_ccw(&turn_ccw(grid)))
+    }
+
+    fn flip_horizontally(grid: &Grid) -> Grid {
+        grid.iter()
+            .map(|row| row.iter().rev().copied().collect())
+            .collect()
+    }
+
+    fn flip_vertically(grid: &Grid) -> Grid {
+        grid.iter().rev().cloned().collect()
+    }
+
+    /// Shows the grid as the orientation describes, counter-clockwise turns
+    /// followed by the flip.
+    fn apply(orientation: Orientation, grid: &Grid) -> Grid {
+        let half_turn = turn_ccw(&turn_ccw(grid));
+        match orientation {
+            Orientation::Deg0 => grid.clone(),
+            Orientation::Deg90 => turn_ccw(grid),
+            Orientation::Deg180 => half_turn,
+            Orientation::Deg270 => turn_cw(grid),
+            Orientation::Deg0HorFlip => flip_horizontally(grid),
+            Orientation::Deg180HorFlip => flip_horizontally(&half_turn),
+            Orientation::Deg90VerFlip => flip_vertically(&turn_ccw(grid)),
+            Orientation::Deg270VerFlip => flip_vertically(&turn_cw(grid)),
+        }
+    }
+
+    #[test]
+    fn exif_orientations() {
+        let path = env::temp_dir()
+            .join(format!("alloy-orientation-{}.jpg", process::id()));
+        for (i, &expected) in ALL_ORIENTATIONS.iter().enumerate() {
+            let value = i as u16 + 1;
+            fs::write(&path, jpeg_with_orientation(value)).unwrap();
+            let orientation = detect_orientation(&path).unwrap();
+            assert_eq!(orientation, expected, "EXIF orientation {}", value);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn scaled_jpeg_decoding() {
+        let path =
+            env::temp_dir().join(format!("alloy-scaled-{}.jpg", process::id()));
+        image::RgbImage::new(64, 32).save(&path).unwrap();
+        let dimensions = |scale, max| {
+            load_scaled_jpeg(&path, scale, max).unwrap().dimensions()
+        };
+        assert_eq!(dimensions(1, None), (64, 32));
+        assert_eq!(dimensions(2, None), (32, 16));
+        // The decoder only scales by 1/2, 1/4 and 1/8, never below the
+        // requested size
+        assert_eq!(dimensions(1, Some(40)), (64, 32));
+        assert_eq!(dimensions(1, Some(16)), (16, 8));
+        assert_eq!(dimensions(1, Some(10)), (16, 8));
+        assert_eq!(dimensions(2, Some(100)), (32, 16));
+        assert_eq!(
+            downscale(image::RgbaImage::new(64, 32), 40).dimensions(),
+            (40, 20)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rotated_cw() {
+        // A clockwise quarter turn is three counter-clockwise ones
+        assert_eq!(Orientation::Deg0.rotated_cw(1), Orientation::Deg270);
+        assert_eq!(Orientation::Deg0.rotated_cw(3), Orientation::Deg90);
+        assert_eq!(Orientation::Deg270.rotated_cw(1), Orientation::Deg180);
+        assert_eq!(Orientation::Deg90.rotated_cw(1), Orientation::Deg0);
+        assert_eq!(
+            Orientation::Deg0HorFlip.rotated_cw(1),
+            Orientation::Deg270VerFlip
+        );
+        for &orientation in ALL_ORIENTATIONS.iter() {
+            assert_eq!(orientation.rotated_cw(4), orientation);
+            assert_eq!(orientation.rotated_cw(1).rotated_cw(3), orientation);
+        }
+    }
+
+    #[test]
+    fn rotation_composes_with_orientation() {
+        let grid = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        for &orientation in ALL_ORIENTATIONS.iter() {
+            let mut expected = apply(orientation, &grid);
+            for quarter_turns in 0..8 {
+                assert_eq!(
+                    apply(orientation.rotated_cw(quarter_turns), &grid),
+                    expected,
+                    "{:?} turned {} times",
+                    orientation,
+                    quarter_turns
+                );
+                expected = turn_cw(&expected);
+            }
+        }
+    }
+
+    #[test]
+    fn count_levels() {
+        let image = image::RgbaImage::from_fn(4, 1, |x, _| match x {
+            0 | 1 => image::Rgba([255, 0, 0, 255]),
+            2 => image::Rgba([0, 0, 255, 255]),
+            _ => image::Rgba([255, 255, 255, 0]),
+        });
+        let levels = Levels::count(&image);
+        assert_eq!(levels.channels[0][255], 3);
+        assert_eq!(levels.channels[0][0], 1);
+        assert_eq!(levels.channels[1][0], 3);
+        assert_eq!(levels.channels[2][255], 2);
+        assert_eq!(levels.luminance[76], 2);
+        assert_eq!(levels.luminance[29], 1);
+        assert_eq!(levels.luminance[255], 1);
+    }
+}
@@ -19,7 +19,12 @@ use gelatin::{
 };
 use log::trace;
 
+use crate::configuration::Enumeration;
+
+pub mod color;
+pub mod filter;
 pub mod image_loader;
+pub mod thumbnailer;
 use self::{directory::DirItem, image_loader::*};
 
 mod pending_requests;
@@ -124,6 +129,11 @@ pub struct AnimationFrameTexture {
     /// The total height of the image. This equals to the sum of the heights of the
     /// textures from a single column of the grid
     pub h: u32,
+
+    /// The size the image is shown at. This differs from `w` and `h` if the
+    /// image was decoded at a smaller size than the one in the file.
+    pub original_w: u32,
+    pub original_h: u32,
 }
 impl AnimationFrameTexture {
     pub fn from_image(
@@ -171,6 +181,8 @@ impl AnimationFrameTexture {
             orientation,
             w,
             h,
+            original_w: w,
+            original_h: h,
             cell_step_size: max_size,
             grid_rows,
             grid_cols,
@@ -180,8 +192,12 @@ impl AnimationFrameTexture {
     pub fn oriented_dimensions(&self) -> (u32, u32) {
         use Orientation::*;
         match self.orientation {
-            Deg0 | Deg0HorFlip | Deg180 | Deg180HorFlip => (self.w, self.h),
-            Deg90 | Deg90VerFlip | Deg270 | Deg270VerFlip => (self.h, self.w),
+            Deg0 | Deg0HorFlip | Deg180 | Deg180HorFlip => {
+                (self.original_w, self.original_h)
+            }
+            Deg90 | Deg90VerFlip | Deg270 | Deg270VerFlip => {
+                (self.original_h, self.original_w)
+            }
         }
     }
 }
@@ -252,6 +268,18 @@ struct CachedTexture {
     /// - `true` if this failed to load,
     failed: bool,
 
+    /// The image was downscaled because it's larger than `max_dimension`
+    downscaled: bool,
+
+    /// The image was decoded at a smaller size by `jpeg_decode_scale`
+    reduced_quality: bool,
+
+    /// The size of the image in the file, if it was decoded at a smaller size
+    original_size: Option<(u32, u32)>,
+
+    /// The levels of the first frame, once it's loaded
+    levels: Option<Rc<Levels>>,
+
     /// If the target file is an image this vector will have a single texture once the
     /// image uploaded to the GPU. If the target file is an animated image like a gif,
     /// these the frames
@@ -285,6 +313,11 @@ pub struct ImageCache {
     pending_requests: PendingRequests,
     texture_cache: BTreeMap<u32, CachedTexture>,
     loader: ImageLoader,
+
+    /// The file that's loaded without regard to `max_dimension`
+    full_resolution: Option<PathBuf>,
+    /// The file that's loaded without regard to `jpeg_decode_scale`
+    full_quality: Option<PathBuf>,
 }
 
 /// This is a store for the supported images loaded from a folder
@@ -295,9 +328,21 @@ impl ImageCache {
 
     /// # Arguments
     /// * `capacity` - Number of bytes. The last image loaded will be the one at which the allocated memory reaches or exceeds capacity
-    pub fn new(capacity: isize, threads: u32) -> ImageCache {
+    /// * `settings` - Passed on to the loader threads
+    /// * `enumeration` - Determines when the contents of a folder are listed
+    /// * `show_hidden` - Include hidden files when listing a folder
+    /// * `follow_symlinks` - Descend into symlinked folders when listing
+    ///   subfolders
+    pub fn new(
+        capacity: isize,
+        threads: u32,
+        settings: DecodeSettings,
+        enumeration: Enumeration,
+        show_hidden: bool,
+        follow_symlinks: bool,
+    ) -> ImageCache {
         ImageCache {
-            dir: Directory::new(),
+            dir: Directory::new(enumeration, show_hidden, follow_symlinks),
             //current_file_idx: 0,
             current_frame_idx: 0,
 
@@ -307,17 +352,43 @@ impl ImageCache {
 
             pending_requests: PendingRequests::new(),
             texture_cache: BTreeMap::new(),
-            loader: ImageLoader::new(threads),
+            loader: ImageLoader::new(threads, settings),
+            full_resolution: None,
+            full_quality: None,
         }
     }
 
-    pub fn current_filename(&self) -> Option<OsString> {
-        self.dir.curr_filename()
+    pub fn current_file_path(&self) -> Option<PathBuf> {
+        self.dir.curr_descriptor().map(|desc| desc.path.clone())
     }
 
-    pub fn current_file_path(&self) -> Option<PathBuf> {
-        self.current_filename()
-            .map(|name| self.dir.path().join(name))
+    /// Adds the file, or the files of the folder, after the files of the
+    /// current directory
+    pub fn append(&mut self, path: &Path) -> Result<()> {
+        self.dir.append(path)?;
+        Ok(())
+    }
+
+    /// Drops the current file from the cache so that it's decoded again the
+    /// next time it's requested.
+    pub fn forget_current(&mut self) {
+        if let Some(desc) = self.dir.curr_descriptor() {
+            if let Some(texture) = self.texture_cache.remove(&desc.request_id) {
+                self.remaining_capacity +=
+                    get_anim_size_estimate(&texture.frames);
+            }
+        }
+    }
+
+    /// Forgets the files added with `append`
+    pub fn clear_appended(&mut self) {
+        self.dir.clear_appended();
+    }
+
+    /// See `Directory::browse_folder_of`
+    pub fn browse_folder_of(&mut self, file: &Path) -> Result<()> {
+        self.dir.browse_folder_of(file)?;
+        Ok(())
     }
 
     /// Returns `None` when the directory hasn't finished filtering image files.
@@ -330,6 +401,11 @@ impl ImageCache {
         self.dir.image_count()
     }
 
+    /// Returns `None` when the directory hasn't finished filtering image files.
+    pub fn file_path_at_index(&mut self, index: usize) -> Option<PathBuf> {
+        self.dir.image_by_index(index).map(|item| item.path.clone())
+    }
+
     fn curr_dir_item(&self) -> Result<DirItem> {
         if let Some(desc) = self.dir.curr_descriptor() {
             Ok(desc.clone())
@@ -338,6 +414,44 @@ impl ImageCache {
         }
     }
 
+    /// Returns true if the current image was downscaled because it's larger
+    /// than `max_dimension`.
+    pub fn current_downscaled(&self) -> bool {
+        self.dir
+            .curr_descriptor()
+            .and_then(|desc| self.texture_cache.get(&desc.request_id))
+            .is_some_and(|texture| texture.downscaled)
+    }
+
+    /// Returns the levels of the current image if its first frame is loaded.
+    pub fn current_levels(&self) -> Option<Rc<Levels>> {
+        self.dir
+            .curr_descriptor()
+            .and_then(|desc| self.texture_cache.get(&desc.request_id))
+            .and_then(|texture| texture.levels.clone())
+    }
+
+    /// Makes the next load of the file ignore `max_dimension`. Loading any
+    /// other file at full resolution reverts this.
+    pub fn set_full_resolution(&mut self, path: PathBuf) {
+        self.full_resolution = Some(path);
+    }
+
+    /// Returns true if the current image was decoded at a smaller size
+    /// because of `jpeg_decode_scale`.
+    pub fn current_reduced_quality(&self) -> bool {
+        self.dir
+            .curr_descriptor()
+            .and_then(|desc| self.texture_cache.get(&desc.request_id))
+            .is_some_and(|texture| texture.reduced_quality)
+    }
+
+    /// Makes the next load of the file ignore `jpeg_decode_scale`. Loading
+    /// any other file at full quality reverts this.
+    pub fn set_full_quality(&mut self, path: PathBuf) {
+        self.full_quality = Some(path);
+    }
+
     /// Returns tru if and only if the current image has been fully loaded and it has a single frame.
     pub fn loaded_still_image(&self) -> bool {
         if let Some(desc) = self.dir.curr_descriptor() {
@@ -413,13 +527,21 @@ impl ImageCache {
         }
 
         let prev_img_index = self.dir.curr_img_index();
-        if let Some(target_file_name) = target_file_name {
-            self.change_directory_with_filename(&parent, &target_file_name)?;
-        } else {
-            self.change_directory(&parent)?;
-            self.current_frame_idx = 0;
+        // Appended files are already in the list even though they are from a
+        // different folder
+        let appended = self.dir.select_appended(path);
+        if !appended {
+            if let Some(target_file_name) = target_file_name {
+                self.change_directory_with_filename(
+                    &parent,
+                    &target_file_name,
+                )?;
+            } else {
+                self.change_directory(&parent)?;
+                self.current_frame_idx = 0;
+            }
         }
-        if self.dir.path() != parent {
+        if !appended && self.dir.path() != parent {
             let DirItem { path, request_id } = self.curr_dir_item()?;
             self.send_request_for_file(
                 path,
@@ -518,6 +640,11 @@ impl ImageCache {
             } else {
                 bail!("No file is open");
             }
+        } else if self.dir.enumerating()
+            && !self.dir.jump_stays_within(file_jump_count as isize)
+        {
+            // Wrapping around has to wait until the whole folder is listed.
+            return Err(Error::from_kind(ErrorKind::WaitingOnDirFilter));
         } else {
             self.current_frame_idx = 0;
         }
@@ -672,7 +799,13 @@ impl ImageCache {
     ) -> Result<Option<AnimationFrameTexture>> {
         use std::collections::btree_map::Entry;
         match load_result {
-            LoadResult::Start { req_id, metadata } => {
+            LoadResult::Start {
+                req_id,
+                metadata,
+                downscaled,
+                reduced_quality,
+                original_size,
+            } => {
                 let curr_mod_time = metadata.modified().ok();
                 if let Some(cancelled) =
                     self.pending_requests.cancelled(&req_id)
@@ -691,6 +824,10 @@ impl ImageCache {
                             fully_loaded: false,
                             mod_time: curr_mod_time,
                             failed: false,
+                            downscaled,
+                            reduced_quality,
+                            original_size,
+                            levels: None,
                             frames: Vec::new(),
                         });
                     }
@@ -711,6 +848,10 @@ impl ImageCache {
                             let mut_entry = entry.get_mut();
                             mut_entry.frames.clear();
                             mut_entry.mod_time = curr_mod_time;
+                            mut_entry.downscaled = downscaled;
+                            mut_entry.reduced_quality = reduced_quality;
+                            mut_entry.original_size = original_size;
+                            mut_entry.levels = None;
                         }
                     }
                 }
@@ -734,18 +875,28 @@ impl ImageCache {
                 let size_estimate =
                     get_image_size_estimate(image.width(), image.height());
                 if let Some(entry) = self.texture_cache.get_mut(&req_id) {
-                    let anim_frame = AnimationFrameTexture::from_image(
+                    let mut anim_frame = AnimationFrameTexture::from_image(
                         display,
                         image,
                         delay_nano,
                         orientation,
                     )?;
+                    if let Some((w, h)) = entry.original_size {
+                        anim_frame.original_w = w;
+                        anim_frame.original_h = h;
+                    }
                     entry.frames.push(anim_frame.clone());
                     self.remaining_capacity -= size_estimate;
                     return Ok(Some(anim_frame));
                 }
                 Ok(None)
             }
+            LoadResult::Levels { req_id, levels } => {
+                if let Some(entry) = self.texture_cache.get_mut(&req_id) {
+                    entry.levels = Some(Rc::from(levels));
+                }
+                Ok(None)
+            }
             LoadResult::Done { req_id } => {
                 if let Some(tex) = self.texture_cache.get_mut(&req_id) {
                     tex.fully_loaded = true;
@@ -864,9 +1015,15 @@ impl ImageCache {
         if self.pending_requests.contains(&req_id) {
             return false;
         }
+        let full_resolution =
+            self.full_resolution.as_deref() == Some(file_path.as_path());
+        let full_quality = full_resolution
+            || self.full_quality.as_deref() == Some(file_path.as_path());
         let request = LoadRequest {
             req_id,
             path: file_path,
+            full_resolution,
+            full_quality,
         };
         self.pending_requests.add_request(request.clone());
         self.loader.send_load_request(request);
@@ -0,0 +1,240 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    env, fs,
+    hash::{Hash, Hasher},
+    io::Read,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use gelatin::image::{self, Rgba, RgbaImage};
+use lazy_static::lazy_static;
+
+use super::image_loader::errors::*;
+use crate::{
+    configuration::ConfigImageSection,
+    input_handling::substitute_command_parameters,
+};
+
+/// The number of generated images kept around so that revisiting a file
+/// doesn't require running the command again.
+const MAX_CACHED_RESULTS: usize = 8;
+
+/// The command is killed if it runs longer than this, so that it can't hold
+/// up a loader thread.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Makes the names of the output folders unique within the process.
+static OUTPUT_DIR_COUNT: AtomicU32 = AtomicU32::new(0);
+
+lazy_static! {
+    /// The `thumbnail_extensions`, so that the files are listed in the folder
+    /// even though they can't be decoded.
+    static ref EXTENSIONS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+}
+
+/// Returns true if the file has one of the `thumbnail_extensions`.
+pub fn has_thumbnail_extension(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => EXTENSIONS
+            .read()
+            .unwrap()
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(ext)),
+        None => false,
+    }
+}
+
+#[derive(Default)]
+struct ThumbnailCache {
+    /// Input hashes in the order they were inserted, oldest first.
+    order: VecDeque<u64>,
+    results: HashMap<u64, RgbaImage>,
+}
+
+/// Produces an image with the external `thumbnail_command` for the files that
+/// can't be decoded, and for the files with the `thumbnail_extensions`.
+///
+/// The command may write the image to its standard output in any of the
+/// supported formats, or save it as a PNG to the path that `${out}` is
+/// replaced with.
+pub struct Thumbnailer {
+    command: String,
+    args: Vec<String>,
+    cache: Mutex<ThumbnailCache>,
+}
+
+impl Thumbnailer {
+    /// Returns `None` if there's no `thumbnail_command`.
+    pub fn from_config(config: &ConfigImageSection) -> Option<Thumbnailer> {
+        let command = config.thumbnail_command.clone()?;
+        if let Some(ref extensions) = config.thumbnail_extensions {
+            let mut registered = EXTENSIONS.write().unwrap();
+            registered.clear();
+            registered.extend(
+                extensions
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_owned()),
+            );
+        }
+        Some(Thumbnailer {
+            command,
+            args: config.thumbnail_args.clone().unwrap_or_default(),
+            cache: Mutex::new(ThumbnailCache::default()),
+        })
+    }
+
+    /// Returns the image made by the command. If the command fails for any
+    /// reason, a warning is printed and a placeholder is returned instead.
+    pub fn load(&self, path: &Path) -> RgbaImage {
+        let hash = input_hash(path);
+        if let Some(cached) = self.cache.lock().unwrap().results.get(&hash) {
+            return cached.clone();
+        }
+        match self.run(path, hash) {
+            Ok(image) => {
+                let mut cache = self.cache.lock().unwrap();
+                if cache.order.len() >= MAX_CACHED_RESULTS {
+                    if let Some(oldest) = cache.order.pop_front() {
+                        cache.results.remove(&oldest);
+                    }
+                }
+                cache.order.push_back(hash);
+                cache.results.insert(hash, image.clone());
+                image
+            }
+            Err(e) => {
+                eprintln!(
+                    "Warning: the thumbnail command failed for {:?}, showing a placeholder.\n    {}",
+                    path, e
+                );
+                placeholder()
+            }
+        }
+    }
+
+    fn run(&self, path: &Path, hash: u64) -> Result<RgbaImage> {
+        let out_dir = OutputDir::create()?;
+        let out_path = out_dir.0.join(format!("{:016x}.png", hash));
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&out_path)?;
+        let img_path = path.to_string_lossy();
+        let out = out_path.to_string_lossy();
+        let mut var_map = HashMap::with_capacity(2);
+        var_map.insert("${img}", img_path.as_ref());
+        var_map.insert("${out}", out.as_ref());
+
+        let mut child = Command::new(&self.command)
+            .args(
+                self.args
+                    .iter()
+                    .map(|arg| substitute_command_parameters(arg, &var_map)),
+            )
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        // Read on another thread so that a full pipe can't stall the command
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut data = Vec::new();
+            let _ = stdout.read_to_end(&mut data);
+            data
+        });
+        let status = match wait_with_timeout(&mut child, COMMAND_TIMEOUT)? {
+            Some(status) => status,
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!(
+                    "The thumbnail command didn't finish within {} seconds",
+                    COMMAND_TIMEOUT.as_secs()
+                );
+            }
+        };
+        if !status.success() {
+            bail!("The thumbnail command exited with {}", status);
+        }
+        let stdout = reader.join().unwrap_or_default();
+        let data = if stdout.is_empty() {
+            fs::read(&out_path)?
+        } else {
+            stdout
+        };
+        Ok(image::load_from_memory(&data)?.into_rgba8())
+    }
+}
+
+/// A folder in the temporary directory that only this user can access, which
+/// the command saves the image to. It's removed when this is dropped.
+struct OutputDir(PathBuf);
+
+impl OutputDir {
+    fn create() -> Result<OutputDir> {
+        let count = OUTPUT_DIR_COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!(
+            "alloy-thumbnail-{}-{}",
+            process::id(),
+            count
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        // Fails if the path exists, so it can't be a folder or a link that
+        // someone else made
+        builder.create(&path)?;
+        Ok(OutputDir(path))
+    }
+}
+
+impl Drop for OutputDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Waits for the child to exit. Returns `None` if it's still running after
+/// `timeout`.
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// A gray square that stands in for an image that couldn't be made.
+fn placeholder() -> RgbaImage {
+    RgbaImage::from_pixel(256, 256, Rgba([128, 128, 128, 255]))
+}
+
+/// Identifies the contents of the file by its path, size and modification
+/// time, so that a changed file is passed to the command again.
+fn input_hash(path: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    if let Ok(metadata) = fs::metadata(path) {
+        metadata.len().hash(&mut hasher);
+        metadata.modified().ok().hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -1,11 +1,19 @@
-use std::{cell::RefCell, collections::HashMap, process::Command, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+    process::Command,
+    rc::Rc,
+    thread,
+};
 
 use gelatin::glium::glutin::event::ModifiersState;
 use lazy_static::lazy_static;
 
-use crate::configuration::Configuration;
+use crate::configuration::{self, Configuration};
 
 pub static TOGGLE_FULLSCREEN_NAME: &str = "toggle_fullscreen";
+pub static TOGGLE_BOTTOM_BAR_NAME: &str = "toggle_bottom_bar";
 pub static ESCAPE_NAME: &str = "escape";
 pub static IMG_NEXT_NAME: &str = "img_next";
 pub static IMG_PREV_NAME: &str = "img_prev";
@@ -26,6 +34,88 @@ pub static PAN_LEFT_NAME: &str = "pan_left";
 pub static PAN_RIGHT_NAME: &str = "pan_right";
 pub static PAN_UP_NAME: &str = "pan_up";
 pub static PAN_DOWN_NAME: &str = "pan_down";
+/// Only an action with the `wallpaper` feature.
+pub static SET_WALLPAPER_NAME: &str = "set_wallpaper";
+pub static TOGGLE_GRAYSCALE_NAME: &str = "toggle_grayscale";
+pub static TOGGLE_HISTOGRAM_NAME: &str = "toggle_histogram";
+pub static SLIDESHOW_RESUME_NAME: &str = "slideshow_resume";
+pub static ROTATE_CW_NAME: &str = "rotate_cw";
+pub static ROTATE_CCW_NAME: &str = "rotate_ccw";
+pub static SET_FOLDER_ROTATION_NAME: &str = "set_folder_rotation";
+pub static TOGGLE_ANTIALIAS_THIS_IMAGE_NAME: &str =
+    "toggle_antialias_this_image";
+pub static CLEAR_ANTIALIAS_OVERRIDE_NAME: &str = "clear_antialias_override";
+pub static RESET_FIT_OVERRIDE_NAME: &str = "reset_fit_override";
+pub static BROWSE_FOLDER_NAME: &str = "browse_folder";
+pub static LOAD_FULL_RESOLUTION_NAME: &str = "load_full_resolution";
+pub static CYCLE_PALETTE_NAME: &str = "cycle_palette";
+pub static COPY_METADATA_NAME: &str = "copy_metadata";
+pub static OPEN_LOCATION_NAME: &str = "open_location";
+pub static PASTE_IMAGE_NAME: &str = "paste_image";
+pub static NEW_WINDOW_NAME: &str = "new_window";
+pub static GOTO_FILE_NAME: &str = "goto_file";
+
+/// Followed by the name of a tag, like `tag:keep`, these add the image to the
+/// tag, remove it from the tag, or jump to the next image with the tag.
+pub static TAG_PREFIX: &str = "tag:";
+pub static UNTAG_PREFIX: &str = "untag:";
+pub static NEXT_TAGGED_PREFIX: &str = "next_tagged:";
+
+/// The actions that take a parameter after the prefix.
+pub static ACTION_PREFIXES: &[&str] =
+    &[TAG_PREFIX, UNTAG_PREFIX, NEXT_TAGGED_PREFIX];
+
+/// The argument of a command that's replaced by the files with the tag,
+/// like `${tagged:keep}`. Each file becomes an argument of its own.
+const TAGGED_PARAMETER_PREFIX: &str = "${tagged:";
+
+/// Every action that can be bound in the `[bindings]` section of the config.
+pub static ACTION_NAMES: &[&str] = &[
+    TOGGLE_FULLSCREEN_NAME,
+    TOGGLE_BOTTOM_BAR_NAME,
+    ESCAPE_NAME,
+    IMG_NEXT_NAME,
+    IMG_PREV_NAME,
+    IMG_ORIG_NAME,
+    IMG_FIT_NAME,
+    IMG_FIT_BEST_NAME,
+    IMG_DEL_NAME,
+    IMG_COPY_NAME,
+    PAN_NAME,
+    PLAY_ANIM_NAME,
+    PLAY_PRESENT_NAME,
+    PLAY_PRESENT_RND_NAME,
+    TOGGLE_ANTIALIAS_NAME,
+    SET_AUTOMATIC_ANTIALIAS_NAME,
+    ZOOM_IN_NAME,
+    ZOOM_OUT_NAME,
+    PAN_LEFT_NAME,
+    PAN_RIGHT_NAME,
+    PAN_UP_NAME,
+    PAN_DOWN_NAME,
+    #[cfg(feature = "wallpaper")]
+    SET_WALLPAPER_NAME,
+    TOGGLE_GRAYSCALE_NAME,
+    TOGGLE_HISTOGRAM_NAME,
+    SLIDESHOW_RESUME_NAME,
+    ROTATE_CW_NAME,
+    ROTATE_CCW_NAME,
+    SET_FOLDER_ROTATION_NAME,
+    TOGGLE_ANTIALIAS_THIS_IMAGE_NAME,
+    CLEAR_ANTIALIAS_OVERRIDE_NAME,
+    RESET_FIT_OVERRIDE_NAME,
+    BROWSE_FOLDER_NAME,
+    LOAD_FULL_RESOLUTION_NAME,
+    CYCLE_PALETTE_NAME,
+    COPY_METADATA_NAME,
+    OPEN_LOCATION_NAME,
+    PASTE_IMAGE_NAME,
+    NEW_WINDOW_NAME,
+    GOTO_FILE_NAME,
+];
+
+/// The modifiers that may precede the key in a binding, like `Alt+A`.
+pub static MODIFIER_NAMES: &[&str] = &["alt", "ctrl", "logo", "cmdctrl"];
 
 lazy_static! {
     pub static ref DEFAULT_BINDINGS: HashMap<&'static str, Vec<&'static str>> = {
@@ -49,6 +139,15 @@ lazy_static! {
     };
 }
 
+/// Returns true if the action is in `ACTION_NAMES`, or is one of the
+/// `ACTION_PREFIXES` followed by a parameter.
+pub fn is_action_name(name: &str) -> bool {
+    ACTION_NAMES.contains(&name)
+        || ACTION_PREFIXES.iter().any(|prefix| {
+            name.strip_prefix(prefix).is_some_and(|p| !p.is_empty())
+        })
+}
+
 pub fn char_to_input_key(ch: char) -> String {
     let mut input_key = String::with_capacity(8);
     if ch == ' ' {
@@ -63,7 +162,7 @@ pub fn char_to_input_key(ch: char) -> String {
     input_key
 }
 
-fn substitute_command_parameters(
+pub fn substitute_command_parameters(
     string: &str,
     var_map: &HashMap<&str, &str>,
 ) -> String {
@@ -78,13 +177,17 @@ fn substitute_command_parameters(
 /// Note: img_path and folder_path both have to be str instead of Path because we
 /// wouldn't be able to construct a command from them if they cannot be converted to
 /// valid UTF-8.
+///
+/// Returns true if a command with `advance_after` succeeded.
 pub fn execute_triggered_commands(
     config: Rc<RefCell<Configuration>>,
     input_key: &str,
     modifiers: ModifiersState,
     img_path: &str,
     folder_path: &str,
-) {
+    tags: &BTreeMap<String, BTreeSet<String>>,
+) -> bool {
+    let mut advance = false;
     let config = config.borrow();
     if let Some(ref commands) = config.commands {
         let mut var_map = HashMap::with_capacity(2);
@@ -92,23 +195,96 @@ pub fn execute_triggered_commands(
         var_map.insert("${folder}", folder_path);
         for command in commands.iter() {
             if keys_triggered(&command.input, input_key, modifiers) {
-                let mut cmd = Command::new(&command.program);
-                if let Some(ref args) = command.args {
-                    cmd.args(args.iter().map(|arg| {
-                        substitute_command_parameters(arg, &var_map)
-                    }));
-                }
-                if let Some(ref envs) = command.envs {
-                    cmd.envs(envs.iter().map(|env_var| {
-                        (env_var.name.as_str(), env_var.value.as_str())
-                    }));
-                }
-                if let Err(e) = cmd.status() {
-                    eprintln!("Error while executing the following user command. See the error below.\n{:?}\nError: {:?}", command, e);
+                let mut cmd = build_command(command, &var_map, tags);
+                match cmd.status() {
+                    Ok(status) => {
+                        advance |= status.success()
+                            && command.advance_after.unwrap_or(false);
+                    }
+                    Err(e) => {
+                        eprintln!("Error while executing the following user command. See the error below.\n{:?}\nError: {:?}", command, e);
+                    }
                 }
             }
         }
     }
+    advance
+}
+
+/// Starts all custom commands that list the event in `on_event`. `path` is
+/// the image the event is about. Unlike the commands run by keys these
+/// aren't waited for, so a slow command doesn't hold up the navigation.
+pub fn execute_event_commands(
+    config: &Configuration,
+    event: &str,
+    path: &Path,
+    tags: &BTreeMap<String, BTreeSet<String>>,
+) {
+    let commands = match config.commands {
+        Some(ref commands) => commands,
+        None => return,
+    };
+    let folder_path = path.parent().unwrap_or_else(|| Path::new(""));
+    let (img_path, folder_path) = match (path.to_str(), folder_path.to_str()) {
+        (Some(img_path), Some(folder_path)) => (img_path, folder_path),
+        _ => {
+            eprintln!(
+                "Could not convert the image path to utf8. Path: '{:?}'",
+                path
+            );
+            return;
+        }
+    };
+    let mut var_map = HashMap::with_capacity(3);
+    var_map.insert("${img}", img_path);
+    var_map.insert("${folder}", folder_path);
+    var_map.insert("${event}", event);
+    for command in commands.iter() {
+        let triggered = match command.on_event {
+            Some(ref events) => events.iter().any(|e| e == event),
+            None => false,
+        };
+        if !triggered {
+            continue;
+        }
+        match build_command(command, &var_map, tags).spawn() {
+            Ok(mut child) => {
+                // Wait on a separate thread so that the process is reaped
+                thread::spawn(move || child.wait());
+            }
+            Err(e) => {
+                eprintln!("Error while executing the following user command. See the error below.\n{:?}\nError: {:?}", command, e);
+            }
+        }
+    }
+}
+
+fn build_command(
+    command: &configuration::Command,
+    var_map: &HashMap<&str, &str>,
+    tags: &BTreeMap<String, BTreeSet<String>>,
+) -> Command {
+    let mut cmd = Command::new(&command.program);
+    for arg in command.args.iter().flatten() {
+        let tag = arg
+            .strip_prefix(TAGGED_PARAMETER_PREFIX)
+            .and_then(|rest| rest.strip_suffix('}'));
+        match tag {
+            Some(tag) => {
+                cmd.args(tags.get(tag).into_iter().flatten());
+            }
+            None => {
+                cmd.arg(substitute_command_parameters(arg, var_map));
+            }
+        }
+    }
+    if let Some(ref envs) = command.envs {
+        cmd.envs(
+            envs.iter()
+                .map(|env_var| (env_var.name.as_str(), env_var.value.as_str())),
+        );
+    }
+    cmd
 }
 
 pub fn keys_triggered<S: AsRef<str>>(
@@ -173,3 +349,59 @@ pub fn action_triggered(
         keys_triggered(keys.as_slice(), input_key, modifiers)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_names() {
+        assert!(is_action_name(IMG_NEXT_NAME));
+        assert!(is_action_name("tag:keep"));
+        assert!(is_action_name("untag:keep"));
+        assert!(is_action_name("next_tagged:to delete"));
+        assert!(!is_action_name("tag:"));
+        assert!(!is_action_name("next_tagged"));
+        assert!(!is_action_name("img_nxt"));
+        assert!(!is_action_name("keep"));
+    }
+
+    #[test]
+    fn command_arguments() {
+        let command = configuration::Command {
+            program: "echo".into(),
+            args: Some(vec![
+                "--file=${img}".into(),
+                "${tagged:keep}".into(),
+                "${tagged:none}".into(),
+                "${tagged:keep".into(),
+                "${folder}".into(),
+            ]),
+            ..Default::default()
+        };
+        let mut var_map = HashMap::new();
+        var_map.insert("${img}", "/pictures/a.jpg");
+        var_map.insert("${folder}", "/pictures");
+        let mut tags = BTreeMap::new();
+        tags.insert(
+            "keep".to_owned(),
+            ["/pictures/a.jpg", "/pictures/b.jpg"]
+                .iter()
+                .map(|&path| path.to_owned())
+                .collect(),
+        );
+        let cmd = build_command(&command, &var_map, &tags);
+        assert_eq!(cmd.get_program(), "echo");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "--file=/pictures/a.jpg",
+                "/pictures/a.jpg",
+                "/pictures/b.jpg",
+                "${tagged:keep",
+                "/pictures",
+            ]
+        );
+    }
+}
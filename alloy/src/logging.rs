@@ -0,0 +1,194 @@
+//! Sets up the logger from the `[logging]` section of the config.
+
+use std::{
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use directories_next::BaseDirs;
+use env_logger::{Builder, Target};
+use log::{Level, LevelFilter};
+
+use crate::configuration::ConfigLoggingSection;
+
+/// The log file is rotated when it grows larger than this unless the config
+/// sets `max_bytes`.
+const DEFAULT_MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+static LOGS_TO_FILE: AtomicBool = AtomicBool::new(false);
+
+/// Initializes the logger. Without a config the logger behaves as before,
+/// taking its level from `RUST_LOG` and writing to stderr. `RUST_LOG`
+/// overrides the configured level.
+pub fn init(config: Option<&ConfigLoggingSection>) {
+    let mut builder = Builder::new();
+    if let Some(level) = config.and_then(|c| c.level.as_deref()) {
+        // Invalid levels are reported by `Configuration::validate`
+        if let Ok(level) = level.parse::<LevelFilter>() {
+            builder.filter_level(level);
+        }
+    }
+    builder.parse_env("RUST_LOG");
+
+    if let Some(file) = config.and_then(|c| c.file.as_deref()) {
+        let path = expand_path(file);
+        let max_bytes = config
+            .and_then(|c| c.max_bytes)
+            .unwrap_or(DEFAULT_MAX_LOG_BYTES);
+        match RotatingFile::open(path.clone(), max_bytes) {
+            Ok(file) => {
+                builder.target(Target::Pipe(Box::new(file)));
+                LOGS_TO_FILE.store(true, Ordering::Relaxed);
+            }
+            Err(e) => {
+                eprintln!(
+                    "Could not open the log file {:?}, logging to stderr instead. {}",
+                    path, e
+                );
+            }
+        }
+    }
+    builder.init();
+}
+
+/// Prints the message to stderr so that the user sees it, and also logs it
+/// if the log goes to a file.
+pub fn report<M: fmt::Display>(level: Level, message: M) {
+    eprintln!("{}", message);
+    if LOGS_TO_FILE.load(Ordering::Relaxed) {
+        log::log!(level, "{}", message);
+    }
+}
+
+/// Expands a leading `~` to the home directory, and `$NAME` or `${NAME}` to
+/// the value of the environment variable. Unknown variables are left as
+/// they are.
+pub fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        if let Some(dirs) = BaseDirs::new() {
+            expanded.push_str(&dirs.home_dir().to_string_lossy());
+            rest = &rest[1..];
+        }
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, consumed) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+        match env::var(name) {
+            Ok(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => expanded.push_str(&rest[start..start + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+/// Appends to a file, which is renamed to `<name>.1` and started over when it
+/// would grow larger than `max_bytes`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<RotatingFile> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let file = open_append(&path)?;
+        let len = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            len,
+            max_bytes,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.path.as_os_str().to_owned();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        self.file = open_append(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.len > 0 && self.len + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_path_variables() {
+        env::set_var("ALLOY_TEST_LOG_DIR", "/var/log/alloy");
+        assert_eq!(
+            expand_path("$ALLOY_TEST_LOG_DIR/alloy.log"),
+            PathBuf::from("/var/log/alloy/alloy.log")
+        );
+        assert_eq!(
+            expand_path("${ALLOY_TEST_LOG_DIR}.old/alloy.log"),
+            PathBuf::from("/var/log/alloy.old/alloy.log")
+        );
+        // Unknown and unfinished variables are left as they are
+        assert_eq!(
+            expand_path("$ALLOY_TEST_UNSET/${ALLOY_TEST_UNSET}/a.log"),
+            PathBuf::from("$ALLOY_TEST_UNSET/${ALLOY_TEST_UNSET}/a.log")
+        );
+        assert_eq!(
+            expand_path("${ALLOY_TEST_LOG_DIR/a.log"),
+            PathBuf::from("${ALLOY_TEST_LOG_DIR/a.log")
+        );
+        assert_eq!(expand_path("costs $5.log"), PathBuf::from("costs $5.log"));
+        assert_eq!(expand_path("a$"), PathBuf::from("a$"));
+    }
+
+    #[test]
+    fn expand_path_home() {
+        let home = BaseDirs::new().unwrap().home_dir().to_owned();
+        assert_eq!(expand_path("~"), home);
+        assert_eq!(expand_path("~/alloy.log"), home.join("alloy.log"));
+        // Only a leading `~` is the home directory
+        assert_eq!(expand_path("~alloy.log"), PathBuf::from("~alloy.log"));
+        assert_eq!(expand_path("logs/~/a.log"), PathBuf::from("logs/~/a.log"));
+    }
+}
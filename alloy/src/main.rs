@@ -6,7 +6,7 @@ extern crate error_chain;
 use std::{
     cell::{Cell, RefCell},
     f32,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -22,6 +22,7 @@ use gelatin::{
     glium::glutin::{
         dpi::{PhysicalPosition, PhysicalSize},
         event::WindowEvent,
+        monitor::MonitorHandle,
         window::Icon,
     },
     image,
@@ -33,28 +34,43 @@ use gelatin::{
     NextUpdate, Widget,
 };
 use lazy_static::lazy_static;
-use log::trace;
+use log::{trace, Level};
 
 use crate::{
-    configuration::{Cache, ConfigWindowSection, Configuration, Theme},
+    configuration::{
+        Cache, Color, ConfigWindowSection, Configuration, Layer, MissingFile,
+        StateBackend, Theme,
+    },
+    input_handling::is_action_name,
+    state_db::{StateDb, STATE_DB_FILE_NAME},
+    theme::ThemeController,
     version::Version,
     widgets::{
         bottom_bar::BottomBar, copy_notification::CopyNotifications,
-        help_screen::*, picture_widget::*,
+        help_screen::*, histogram::Histogram, picture_widget::*,
+        progress_overlay::ProgressOverlay, sidecar_overlay::SidecarOverlay,
     },
 };
 
 mod clipboard_handler;
 mod cmd_line;
 mod configuration;
+mod event_hooks;
 mod handle_panic;
 mod image_cache;
 mod input_handling;
+mod logging;
+mod metadata;
 mod parallel_action;
 mod playback_manager;
 mod shaders;
+mod sidecar;
+mod state_db;
+mod theme;
 mod utils;
 mod version;
+#[cfg(feature = "wallpaper")]
+mod wallpaper;
 mod widgets;
 
 lazy_static! {
@@ -71,31 +87,90 @@ static VISIT_SITE: &[u8] = include_bytes!("../resource/visit-site.png");
 static USAGE: &[u8] = include_bytes!("../resource/usage.png");
 static LEFT_TO_PAN: &[u8] = include_bytes!("../resource/use-left-to-pan.png");
 
+/// Closing the window twice within this duration quits even when `on_close`
+/// is set.
+const FORCE_QUIT_INTERVAL: Duration = Duration::from_secs(2);
+
 // ========================================================
 // Not-so glorious main function
 // ========================================================
 fn main() {
     std::panic::set_hook(Box::new(handle_panic::handle_panic));
-    env_logger::init();
-    trace!("Starting up. Panic hook set, logger initialized.");
 
     // Load configuration and cache files
     let (config_path, cache_path) = get_config_and_cache_paths();
 
     let args = cmd_line::parse_args();
 
+    let config = load_config(&config_path, &args);
+    // The logger is configured by the config, but it's started before
+    // anything else so that the problems with the config are logged too.
+    logging::init(config.as_ref().ok().and_then(|c| c.logging.as_ref()));
+    trace!("Starting up. Panic hook set, logger initialized.");
+    if args.validate_config {
+        validate_config(config);
+    }
+    if args.config_provenance {
+        print_config_provenance(config);
+    }
+
     let cache = Cache::load(&cache_path);
-    let config = Configuration::load(&config_path);
+    let config = match config {
+        Ok(config) => {
+            for diagnostic in config.validate() {
+                let level = match diagnostic.severity {
+                    configuration::Severity::Warning => Level::Warn,
+                    configuration::Severity::Error => Level::Error,
+                };
+                logging::report(level, diagnostic);
+            }
+            config
+        }
+        Err(e) => {
+            logging::report(Level::Error, e);
+            Configuration::default()
+        }
+    };
 
     let first_launch = cache.is_err();
-    let cache = Arc::new(Mutex::new(cache.unwrap_or_default()));
-    let config = Rc::new(RefCell::new(config.unwrap_or_default()));
+    let mut cache = cache.unwrap_or_default();
+    cache.read_only = config
+        .cache
+        .as_ref()
+        .and_then(|c| c.read_only)
+        .unwrap_or(false);
+    let state_backend = config
+        .cache
+        .as_ref()
+        .and_then(|c| c.state_backend)
+        .unwrap_or_default();
+    let db_path = cache_path.with_file_name(STATE_DB_FILE_NAME);
+    // A read-only cache never creates the database, until then the state is
+    // only in the cache file
+    if state_backend == StateBackend::Sqlite
+        && (!cache.read_only || db_path.exists())
+    {
+        let result = StateDb::open(&db_path, cache.read_only)
+            .and_then(|db| cache.image.use_database(db));
+        if let Err(e) = result {
+            logging::report(
+                Level::Error,
+                format!(
+                    "Could not use the state database {:?}, the state is kept in the cache file. {}",
+                    db_path, e
+                ),
+            );
+        }
+    }
+    let cache = Arc::new(Mutex::new(cache));
+    let config = Rc::new(RefCell::new(config));
 
     let mut application = Application::new();
     let window: Rc<Window> = {
         let window_cache = &mut cache.lock().unwrap().window;
         let window_cfg = &config.borrow().window;
         let window_defaults = configuration::CacheWindowSection::default();
+        let (min_w, min_h) = config.borrow().min_window_size();
 
         if let Some(ConfigWindowSection {
             use_last_window_area: Some(false),
@@ -106,6 +181,7 @@ fn main() {
             ..
         }) = window_cfg
         {
+            let work_area = work_area_size(&application, *win_x, *win_y);
             window_cache.win_x = if let Some(x) = win_x {
                 *x
             } else {
@@ -116,16 +192,12 @@ fn main() {
             } else {
                 window_defaults.win_y
             };
-            window_cache.win_w = if let Some(w) = win_w {
-                *w
-            } else {
-                window_defaults.win_w
-            };
-            window_cache.win_h = if let Some(h) = win_h {
-                *h
-            } else {
-                window_defaults.win_h
-            };
+            window_cache.win_w = win_w
+                .and_then(|w| w.resolve(work_area.map(|s| s.width)))
+                .unwrap_or(window_defaults.win_w);
+            window_cache.win_h = win_h
+                .and_then(|h| h.resolve(work_area.map(|s| s.height)))
+                .unwrap_or(window_defaults.win_h);
         } else {
             let right = window_cache.win_x as i64 + window_cache.win_w as i64;
             if right < 20 {
@@ -136,12 +208,15 @@ fn main() {
                 window_cache.win_y = window_defaults.win_y;
             }
         }
+        window_cache.win_w = window_cache.win_w.max(min_w);
+        window_cache.win_h = window_cache.win_h.max(min_h);
         let pos = PhysicalPosition::new(window_cache.win_x, window_cache.win_y);
         let size = PhysicalSize::new(window_cache.win_w, window_cache.win_h);
         let window_desc = WindowDescriptor::builder()
             .icon(Some(make_icon()))
             .size(size)
             .position(Some(pos))
+            .min_size(Some(PhysicalSize::new(min_w, min_h)))
             .app_id(Some("Alloy".into()))
             .build();
         let window = Window::new(&mut application, window_desc);
@@ -186,24 +261,57 @@ fn main() {
 
     let copy_notifications_widget = Rc::new(Label::new());
     let copy_notifications = CopyNotifications::new(&copy_notifications_widget);
-
-    let bottom_bar = Rc::new(BottomBar::new(&config.borrow()));
+    let progress_overlay_widget = Rc::new(Label::new());
+    let progress_overlay = ProgressOverlay::new(&progress_overlay_widget);
+    let sidecar_overlay_widget = Rc::new(Label::new());
+    let sidecar_overlay = SidecarOverlay::new(&sidecar_overlay_widget);
+    let histogram_widget = Rc::new(Label::new());
+    let histogram = Histogram::new(&histogram_widget);
+
+    let bottom_bar = Rc::new(BottomBar::new(
+        &config.borrow(),
+        &mut cache.lock().unwrap().window,
+        window.fullscreen(),
+    ));
     let picture_widget = make_picture_widget(
         &window,
         bottom_bar.clone(),
         left_to_pan_hint.clone(),
         copy_notifications,
+        progress_overlay,
+        sidecar_overlay,
+        histogram,
         config.clone(),
         cache.clone(),
     );
 
     if let Some(file_path) = args.file_path {
-        picture_widget.jump_to_path(file_path);
+        let missing_file = config
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.missing_file)
+            .unwrap_or_default();
+        if let Some(path) = existing_path(file_path.into(), missing_file) {
+            picture_widget.jump_to_path(path);
+        }
+    } else {
+        let home = config
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.home_path());
+        if let Some(home) = home {
+            picture_widget.jump_to_path(home);
+        }
     }
 
     let picture_area_container = make_picture_area_container();
     picture_area_container.add_child(picture_widget.clone());
     picture_area_container.add_child(copy_notifications_widget);
+    picture_area_container.add_child(progress_overlay_widget);
+    picture_area_container.add_child(sidecar_overlay_widget);
+    picture_area_container.add_child(histogram_widget);
     picture_area_container.add_child(left_to_pan_hint);
     picture_area_container.add_child(help_screen.clone());
     picture_area_container.add_child(update_notification.clone());
@@ -216,17 +324,35 @@ fn main() {
     let update_check_done = Arc::new(AtomicBool::new(false));
 
     let theme = {
-        Rc::new(Cell::new(match &config.borrow().window {
+        let initial = match &config.borrow().window {
             Some(ConfigWindowSection {
                 theme: Some(theme_cfg),
                 ..
             }) => *theme_cfg,
             _ => cache.lock().unwrap().theme(),
-        }))
+        };
+        Rc::new(ThemeController::new(initial, cache.clone()))
+    };
+
+    let palettes = Rc::new(
+        config
+            .borrow()
+            .window
+            .as_ref()
+            .and_then(|w| w.palettes.clone())
+            .unwrap_or_default(),
+    );
+    let palette = {
+        let name = cache.lock().unwrap().window.palette.clone();
+        let index =
+            name.and_then(|name| palettes.iter().position(|p| p.name == name));
+        Rc::new(Cell::new(index))
     };
 
     let set_theme = {
         let update_label = update_label;
+        let palettes = palettes.clone();
+        let palette = palette.clone();
         let picture_widget = picture_widget.clone();
         let update_notification = update_notification.clone();
         let window = window.clone();
@@ -235,36 +361,60 @@ fn main() {
         let bottom_bar = bottom_bar.clone();
 
         Rc::new(move || {
-            match theme.get() {
-                Theme::Light => {
-                    picture_widget.set_bright_shade(0.96);
-                    window.set_bg_color([0.85, 0.85, 0.85, 1.0]);
-                    update_notification.set_bg_color([0.06, 0.06, 0.06, 1.0]);
-                    update_label
-                        .set_icon(Some(update_label_image_light.clone()));
+            let palette = palette.get().and_then(|i| palettes.get(i));
+            let curr_theme = palette
+                .and_then(|p| p.theme)
+                .map_or_else(|| theme.current_theme(), |t| theme.resolve(t));
+            let dark = curr_theme == Theme::Dark;
+            if dark {
+                picture_widget.set_bright_shade(0.11);
+                window.set_bg_color([0.03, 0.03, 0.03, 1.0]);
+                update_notification.set_bg_color([0.85, 0.85, 0.85, 1.0]);
+                update_label.set_icon(Some(update_label_image.clone()));
+            } else {
+                picture_widget.set_bright_shade(0.96);
+                window.set_bg_color([0.85, 0.85, 0.85, 1.0]);
+                update_notification.set_bg_color([0.06, 0.06, 0.06, 1.0]);
+                update_label.set_icon(Some(update_label_image_light.clone()));
+            }
+            bottom_bar.set_theme(dark, update_available.load(Ordering::SeqCst));
+            if let Some(palette) = palette {
+                if let Some(Color(color)) = palette.background {
+                    window.set_bg_color(color);
                 }
-                Theme::Dark => {
-                    picture_widget.set_bright_shade(0.11);
-                    window.set_bg_color([0.03, 0.03, 0.03, 1.0]);
-                    update_notification.set_bg_color([0.85, 0.85, 0.85, 1.0]);
-                    update_label.set_icon(Some(update_label_image.clone()));
+                if let Some(Color(color)) = palette.bottom_bar {
+                    bottom_bar.widget.set_bg_color(color);
                 }
             }
-            bottom_bar.set_theme(
-                theme.get(),
-                update_available.load(Ordering::SeqCst),
-            );
         })
     };
     set_theme();
     {
-        let cache = cache.clone();
         let set_theme = set_theme.clone();
+        theme.add_on_change(move |_| set_theme());
+    }
+    {
+        let cache = cache.clone();
+        let palette = palette.clone();
         bottom_bar.theme_button.set_on_click(move || {
-            let new_theme = theme.get().switch_theme();
-            theme.set(new_theme);
-            cache.lock().unwrap().set_theme(new_theme);
+            palette.set(None);
+            cache.lock().unwrap().window.palette = None;
+            theme.set_theme_runtime(theme.current_theme().switch_theme());
+        });
+    }
+    {
+        let cache = cache.clone();
+        let set_theme = set_theme.clone();
+        picture_widget.set_on_cycle_palette(move || {
+            if palettes.is_empty() {
+                return None;
+            }
+            let next = palette.get().map_or(0, |i| (i + 1) % palettes.len());
+            palette.set(Some(next));
+            let name = palettes[next].name.clone();
+            cache.lock().unwrap().window.palette = Some(name.clone());
             set_theme();
+            Some(name)
         });
     }
     {
@@ -287,6 +437,7 @@ fn main() {
         });
     }
     {
+        let picture_widget = picture_widget.clone();
         bottom_bar.fit_stretch_button.set_on_click(move || {
             picture_widget.set_img_size_to_fit(true);
         });
@@ -320,6 +471,11 @@ fn main() {
         .as_ref()
         .map(|u| u.check_updates)
         .unwrap_or(true);
+    let update_url = config
+        .borrow()
+        .updates
+        .as_ref()
+        .and_then(|u| u.update_url.clone());
 
     let update_checker_join_handle = {
         let updates = &mut cache.lock().unwrap().updates;
@@ -330,11 +486,15 @@ fn main() {
         if check_updates_enabled && updates.update_check_needed() {
             // kick off a thread that will check for an update in the background
             Some(std::thread::spawn(move || {
-                let has_update = update::check_for_updates();
-                update_available.store(has_update, Ordering::SeqCst);
+                let has_update = update::check_for_updates(update_url);
+                update_available
+                    .store(has_update == Some(true), Ordering::SeqCst);
                 update_check_done.store(true, Ordering::SeqCst);
-                if !has_update {
-                    cache.lock().unwrap().updates.set_update_check_time();
+                let updates = &mut cache.lock().unwrap().updates;
+                match has_update {
+                    Some(false) => updates.set_update_check_time(),
+                    Some(true) => {}
+                    None => updates.set_update_check_failed(),
                 }
             }))
         } else {
@@ -358,6 +518,43 @@ fn main() {
         NextUpdate::WaitUntil(Instant::now() + Duration::from_secs(1))
     });
 
+    let on_close = config
+        .borrow()
+        .window
+        .as_ref()
+        .and_then(|w| w.on_close.clone())
+        // Unknown actions are reported by `Configuration::validate`
+        .filter(|action| is_action_name(action));
+    let idle_quit_secs = config
+        .borrow()
+        .window
+        .as_ref()
+        .and_then(|w| w.idle_quit_secs)
+        .unwrap_or(0);
+    if idle_quit_secs > 0 {
+        add_idle_quit(
+            &mut application,
+            &window,
+            Duration::from_secs(idle_quit_secs),
+            on_close.clone(),
+            picture_widget.clone(),
+        );
+    }
+    if let Some(on_close) = on_close {
+        let picture_widget = picture_widget.clone();
+        let mut last_close_request: Option<Instant> = None;
+        application.set_close_handler(Some(move || {
+            let now = Instant::now();
+            let forced = last_close_request
+                .is_some_and(|last| now - last < FORCE_QUIT_INTERVAL);
+            last_close_request = Some(now);
+            if !forced {
+                picture_widget.trigger_action(&on_close);
+            }
+            forced
+        }));
+    }
+
     application.set_at_exit(Some(move || {
         cache.lock().unwrap().save(cache_path).unwrap();
         if let Some(h) = update_checker_join_handle {
@@ -377,6 +574,41 @@ fn make_icon() -> Icon {
     Icon::from_rgba(rgba.into_raw(), w, h).unwrap()
 }
 
+/// Quits, or runs the `on_close` action, once there was no input for
+/// `idle_duration`.
+fn add_idle_quit(
+    application: &mut Application,
+    window: &Window,
+    idle_duration: Duration,
+    on_close: Option<String>,
+    picture_widget: Rc<PictureWidget>,
+) {
+    let last_input = Rc::new(Cell::new(Instant::now()));
+    {
+        let last_input = last_input.clone();
+        window.add_global_event_handler(move |event| match event {
+            WindowEvent::KeyboardInput { .. }
+            | WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::CursorMoved { .. } => last_input.set(Instant::now()),
+            _ => (),
+        });
+    }
+    application.add_global_event_handler(move |_| {
+        let now = Instant::now();
+        if now >= last_input.get() + idle_duration {
+            // Starts over so that the action only runs once per idle period
+            last_input.set(now);
+            match on_close {
+                Some(ref on_close) => picture_widget.trigger_action(on_close),
+                None => request_exit(),
+            }
+        }
+        NextUpdate::WaitUntil(last_input.get() + idle_duration)
+    });
+}
+
 fn add_window_movement_listener(window: &Window, cache: Arc<Mutex<Cache>>) {
     window.add_global_event_handler(move |event| match event {
         WindowEvent::Resized(new_size) => {
@@ -458,11 +690,15 @@ fn make_update_notification(
     container
 }
 
+#[allow(clippy::too_many_arguments)]
 fn make_picture_widget(
     window: &Rc<Window>,
     bottom_bar: Rc<BottomBar>,
     left_to_pan_hint: Rc<HelpScreen>,
     copy_notifications: CopyNotifications,
+    progress_overlay: ProgressOverlay,
+    sidecar_overlay: SidecarOverlay,
+    histogram: Histogram,
     config: Rc<RefCell<Configuration>>,
     cache: Arc<Mutex<Cache>>,
 ) -> Rc<PictureWidget> {
@@ -472,6 +708,9 @@ fn make_picture_widget(
         bottom_bar,
         left_to_pan_hint,
         copy_notifications,
+        progress_overlay,
+        sidecar_overlay,
+        histogram,
         config,
         cache,
     ));
@@ -486,6 +725,139 @@ fn make_picture_widget(
     picture_widget
 }
 
+/// Loads the configuration from every layer, in the order of their
+/// precedence.
+fn load_config(
+    config_path: &Path,
+    args: &cmd_line::Args,
+) -> Result<Configuration, String> {
+    let system_config_path = get_system_config_path();
+    let mut files = Vec::new();
+    if let Some(ref path) = system_config_path {
+        files.push((Layer::System, path.as_path()));
+    }
+    files.push((Layer::User, config_path));
+
+    let mut overrides = toml::value::Table::new();
+    if args.cache_readonly {
+        let mut cache = toml::value::Table::new();
+        cache.insert("read_only".into(), toml::Value::Boolean(true));
+        overrides.insert("cache".into(), toml::Value::Table(cache));
+    }
+    Configuration::load_layers(&files, overrides)
+}
+
+/// Returns the path that's opened for the path given on the command line.
+/// If it doesn't exist, `missing_file` determines what's returned, or the
+/// process exits.
+fn existing_path(path: PathBuf, missing_file: MissingFile) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path);
+    }
+    match missing_file {
+        MissingFile::Error => {
+            eprintln!("{:?} doesn't exist", path);
+            std::process::exit(1);
+        }
+        MissingFile::OpenFolder => {
+            // The parent of a bare file name is empty
+            let folder = match path.parent() {
+                Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+                parent => parent?,
+            };
+            folder.is_dir().then(|| folder.to_owned())
+        }
+        MissingFile::Blank => None,
+    }
+}
+
+/// Prints the problems found in the configuration and exits. The exit code is
+/// non-zero if the configuration can't be used as it is.
+fn validate_config(config: Result<Configuration, String>) -> ! {
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let diagnostics = config.validate();
+    for diagnostic in diagnostics.iter() {
+        println!("{}", diagnostic);
+    }
+    if diagnostics.is_empty() {
+        println!("No problems found in the configuration");
+    }
+    let has_errors = diagnostics
+        .iter()
+        .any(|d| d.severity == configuration::Severity::Error);
+    std::process::exit(if has_errors { 1 } else { 0 });
+}
+
+/// Prints which layer each configured value comes from and exits.
+fn print_config_provenance(config: Result<Configuration, String>) -> ! {
+    match config {
+        Ok(config) => {
+            for (field, layer) in config.provenance() {
+                println!("{}: {}", field, layer);
+            }
+            println!("content hash: {:016x}", config.content_hash());
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The config shared by every user, which the config of the user overrides.
+fn get_system_config_path() -> Option<PathBuf> {
+    if cfg!(all(unix, not(target_os = "macos"))) {
+        Some(PathBuf::from("/etc/emulsion/cfg.toml"))
+    } else {
+        None
+    }
+}
+
+/// Space on each side of the monitor that's assumed to be taken by taskbars,
+/// panels and the window decorations, in logical pixels. Winit can't tell
+/// where the work area of a monitor is.
+const WORK_AREA_MARGIN: f64 = 48.0;
+
+/// Returns the estimated work area of the monitor containing the given
+/// position, or of the primary monitor if there's no position or no monitor
+/// contains it.
+fn work_area_size(
+    application: &Application,
+    x: Option<i32>,
+    y: Option<i32>,
+) -> Option<PhysicalSize<u32>> {
+    let work_area = |monitor: MonitorHandle| {
+        let size = monitor.size();
+        let margin = (2.0 * WORK_AREA_MARGIN * monitor.scale_factor()) as u32;
+        PhysicalSize::new(
+            size.width.saturating_sub(margin),
+            size.height.saturating_sub(margin),
+        )
+    };
+    let event_loop = &application.event_loop;
+    if let (Some(x), Some(y)) = (x, y) {
+        let containing = event_loop.available_monitors().find(|monitor| {
+            let pos = monitor.position();
+            let size = monitor.size();
+            x >= pos.x
+                && y >= pos.y
+                && (x as i64) < pos.x as i64 + size.width as i64
+                && (y as i64) < pos.y as i64 + size.height as i64
+        });
+        if let Some(monitor) = containing {
+            return Some(work_area(monitor));
+        }
+    }
+    event_loop.primary_monitor().map(work_area)
+}
+
 pub fn get_config_and_cache_paths() -> (PathBuf, PathBuf) {
     let config_folder;
     let cache_folder;
@@ -514,9 +886,9 @@ pub fn get_config_and_cache_paths() -> (PathBuf, PathBuf) {
 
 #[cfg(not(feature = "networking"))]
 mod update {
-    /// Always returns false without the `networking` feature.
-    pub fn check_for_updates() -> bool {
-        false
+    /// Always returns `Some(false)` without the `networking` feature.
+    pub fn check_for_updates(_url: Option<String>) -> Option<bool> {
+        Some(false)
     }
 }
 
@@ -539,10 +911,12 @@ mod update {
         }
     }
 
+    /// Queried when the config doesn't set `update_url`
+    const DEFAULT_URL: &str =
+        "https://api.github.com/repos/ArturKovacs/emulsion/releases/latest";
+
     /// Tries to fetch latest release tag
-    fn latest_release() -> errors::Result<ReleaseInfoJson> {
-        let url =
-            "https://api.github.com/repos/ArturKovacs/emulsion/releases/latest";
+    fn latest_release(url: &str) -> errors::Result<ReleaseInfoJson> {
         let res = ureq::get(url).set("User-Agent", "emulsion").call();
         match res {
             Ok(res) => {
@@ -573,19 +947,20 @@ mod update {
         }
     }
 
-    /// Returns true if updates are available.
-    pub fn check_for_updates() -> bool {
-        match latest_release() {
+    /// Returns true if updates are available, or `None` if the release
+    /// couldn't be fetched from `url`.
+    pub fn check_for_updates(url: Option<String>) -> Option<bool> {
+        match latest_release(url.as_deref().unwrap_or(DEFAULT_URL)) {
             Ok(info) => match compare_release(&info) {
-                Ok(is_newer) => is_newer,
+                Ok(is_newer) => Some(is_newer),
                 Err(err) => {
                     eprintln!("Error parsing release tag: {}", err);
-                    false
+                    Some(false)
                 }
             },
             Err(err) => {
                 eprintln!("Error checking latest release: {}", err);
-                false
+                None
             }
         }
     }
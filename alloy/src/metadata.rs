@@ -0,0 +1,93 @@
+//! Collects the EXIF metadata of images as readable text.
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use exif::{Exif, In, Tag, Value};
+
+fn read_exif(path: &Path) -> Option<Exif> {
+    let file = File::open(path).ok()?;
+    exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+}
+
+/// Returns the EXIF fields of the primary image as `(name, value)` pairs,
+/// like `("Model", "\"X-T30\"")`. Files without EXIF data have no fields.
+///
+/// When `only` is given, just the fields with those names are returned,
+/// compared case-insensitively.
+pub fn exif_fields(
+    path: &Path,
+    only: Option<&[String]>,
+) -> Vec<(String, String)> {
+    let exif = match read_exif(path) {
+        Some(exif) => exif,
+        None => return Vec::new(),
+    };
+    exif.fields()
+        .filter(|field| field.ifd_num == In::PRIMARY)
+        .map(|field| {
+            let value = field.display_value().with_unit(&exif).to_string();
+            (field.tag.to_string(), value)
+        })
+        .filter(|(name, _)| match only {
+            Some(only) => only.iter().any(|n| n.eq_ignore_ascii_case(name)),
+            None => true,
+        })
+        .collect()
+}
+
+/// Formats the metadata as one `name: value` line per field, preceded by the
+/// name of the file.
+pub fn format_metadata(path: &Path, only: Option<&[String]>) -> String {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let fields = exif_fields(path, only);
+    if fields.is_empty() {
+        return format!("{}: no metadata", file_name);
+    }
+    let mut text = file_name;
+    for (name, value) in fields {
+        text.push('\n');
+        text.push_str(&name);
+        text.push_str(": ");
+        text.push_str(&value);
+    }
+    text
+}
+
+/// Returns the latitude and the longitude where the photo was taken in
+/// degrees, negative to the south and the west, if the file has them.
+pub fn gps_coordinates(path: &Path) -> Option<(f64, f64)> {
+    let exif = read_exif(path)?;
+    let latitude = gps_degrees(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef)?;
+    let longitude =
+        gps_degrees(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef)?;
+    Some((latitude, longitude))
+}
+
+/// Reads a coordinate stored as degrees, minutes and seconds, along with the
+/// reference that tells its hemisphere.
+fn gps_degrees(exif: &Exif, tag: Tag, ref_tag: Tag) -> Option<f64> {
+    let dms = match exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(ref dms) if !dms.is_empty() => dms,
+        _ => return None,
+    };
+    let mut degrees = 0.0;
+    for (part, divisor) in dms.iter().zip([1.0, 60.0, 3600.0]) {
+        if part.denom == 0 {
+            return None;
+        }
+        degrees += part.to_f64() / divisor;
+    }
+    let negative = match exif.get_field(ref_tag, In::PRIMARY).map(|f| &f.value)
+    {
+        Some(Value::Ascii(parts)) => parts
+            .first()
+            .is_some_and(|r| r.starts_with(b"S") || r.starts_with(b"W")),
+        _ => false,
+    };
+    Some(if negative { -degrees } else { degrees })
+}
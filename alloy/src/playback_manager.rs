@@ -11,11 +11,18 @@ use gelatin::{glium::Display, window::Window};
 use log::{debug, trace};
 use rand::{seq::SliceRandom, thread_rng};
 
-use crate::image_cache::{self, AnimationFrameTexture, ImageCache};
+use crate::{
+    configuration::Enumeration,
+    image_cache::{
+        self,
+        image_loader::{DecodeSettings, Levels},
+        AnimationFrameTexture, ImageCache,
+    },
+};
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LoadRequest {
     None,
     LoadNext,
@@ -163,10 +170,17 @@ pub struct PlaybackManager {
     // filename: Option<OsString>,
     folder_player: ImgSequencePlayer<FolderPlayback>,
     image_player: ImgSequencePlayer<AnimPlayback>,
+
+    pending_appends: Vec<PathBuf>,
 }
 
 impl PlaybackManager {
-    pub fn new() -> Self {
+    pub fn new(
+        decode_settings: DecodeSettings,
+        enumeration: Enumeration,
+        show_hidden: bool,
+        follow_symlinks: bool,
+    ) -> Self {
         let cache_capaxity = match sys_info::mem_info() {
             Ok(value) => {
                 // value originally reported in KiB
@@ -188,9 +202,17 @@ impl PlaybackManager {
 
         PlaybackManager {
             //playback_state: PlaybackState::Paused,
-            image_cache: ImageCache::new(cache_capaxity, thread_count),
+            image_cache: ImageCache::new(
+                cache_capaxity,
+                thread_count,
+                decode_settings,
+                enumeration,
+                show_hidden,
+                follow_symlinks,
+            ),
             folder_player: ImgSequencePlayer::new(),
             image_player: ImgSequencePlayer::new(),
+            pending_appends: Vec::new(),
         }
     }
 
@@ -236,6 +258,15 @@ impl PlaybackManager {
         self.image_cache.current_dir_len()
     }
 
+    /// Returns None when the folder hasn't finished filtering
+    pub fn file_path_at_index(&mut self, index: usize) -> Option<PathBuf> {
+        self.image_cache.file_path_at_index(index)
+    }
+
+    pub fn current_downscaled(&self) -> bool {
+        self.image_cache.current_downscaled()
+    }
+
     pub fn update_directory(&mut self) -> image_cache::Result<()> {
         debug!("In `update_directory`");
         if let LoadRequest::None = self.folder_player.load_request {
@@ -265,6 +296,67 @@ impl PlaybackManager {
         self.image_player.request_load(LoadRequest::Jump(0));
     }
 
+    /// Adds the file, or the files of the folder, after the files of the
+    /// current directory without changing the shown image.
+    ///
+    /// This happens after the pending file has been opened, so that the file
+    /// is added to the list of the new directory.
+    pub fn append(&mut self, path: PathBuf) {
+        self.pending_appends.push(path);
+    }
+
+    /// Decodes the current file again, keeping the current image on the
+    /// screen until that finishes.
+    pub fn reload_current(&mut self) {
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.image_cache.forget_current();
+            self.request_load(LoadRequest::FilePath(path));
+        }
+    }
+
+    /// Loads the current image again, without downscaling it to fit within
+    /// `max_dimension`.
+    pub fn load_full_resolution(&mut self) {
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.image_cache.set_full_resolution(path);
+            self.reload_current();
+        }
+    }
+
+    pub fn current_reduced_quality(&self) -> bool {
+        self.image_cache.current_reduced_quality()
+    }
+
+    pub fn current_levels(&self) -> Option<Rc<Levels>> {
+        self.image_cache.current_levels()
+    }
+
+    /// Loads the current image again, ignoring `jpeg_decode_scale`.
+    pub fn load_full_quality(&mut self) {
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.image_cache.set_full_quality(path);
+            self.reload_current();
+        }
+    }
+
+    pub fn clear_appended(&mut self) {
+        self.pending_appends.clear();
+        self.image_cache.clear_appended();
+    }
+
+    /// Replaces the list of files with the folder of the current file,
+    /// keeping the current file selected.
+    pub fn browse_folder(&mut self) -> image_cache::Result<()> {
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.pending_appends.clear();
+            self.image_cache.browse_folder_of(&path)?;
+            if let Some(path) = self.image_cache.current_file_path() {
+                self.request_load(LoadRequest::FilePath(path));
+            }
+        }
+        Ok(())
+    }
+
     pub fn image_texture(&self) -> Option<AnimationFrameTexture> {
         self.image_player.image_texture()
     }
@@ -281,6 +373,17 @@ impl PlaybackManager {
             .folder_player
             .update_image(&display, &mut self.image_cache);
         trace!("Folder player next update: {:?}", next_update);
+        if !matches!(self.folder_player.load_request, LoadRequest::FilePath(..))
+        {
+            for path in self.pending_appends.drain(..) {
+                if let Err(e) = self.image_cache.append(&path) {
+                    eprintln!(
+                        "Could not add {:?} to the list of files: {}",
+                        path, e
+                    );
+                }
+            }
+        }
         let new_file = self.folder_player.image_texture();
         let mut file_changed = prev_file.is_none() != new_file.is_none();
         if let (Some(prev), Some(new)) = (prev_file, new_file) {
@@ -513,6 +616,7 @@ impl<P: Playback> ImgSequencePlayer<P> {
             }
         }
         trace!("Attempting actual load in `update_image`");
+        let retry_request = load_request.clone();
         let load_result = match load_request {
             LoadRequest::LoadNext => Some(P::load_next(image_cache, display)),
             LoadRequest::LoadPrevious => {
@@ -546,6 +650,17 @@ impl<P: Playback> ImgSequencePlayer<P> {
                     next_update =
                         gelatin::NextUpdate::WaitUntil(few_millisecs_from_now);
                 }
+                Err(image_cache::errors::Error(
+                    image_cache::errors::ErrorKind::WaitingOnDirFilter,
+                    _,
+                )) => {
+                    // The directory is still being listed, keep the same
+                    // request so that it's carried out once the listing is
+                    // done.
+                    self.load_request = retry_request;
+                    next_update =
+                        gelatin::NextUpdate::WaitUntil(few_millisecs_from_now);
+                }
                 Err(err) => {
                     self.image_texture = None;
                     self.file_path = None;
@@ -1,10 +1,16 @@
 #version 110
 uniform sampler2D tex;
 uniform float bright_shade;
+uniform bool grayscale;
 uniform float lod_level; // textureLod is not available in 1.10
 varying vec2 v_tex_coords;
 void main() {
     vec4 color = texture2D(tex, v_tex_coords);
+    if (grayscale) {
+        // The texture is sRGB so the color is already linear here
+        float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+        color = vec4(vec3(luminance), color.a);
+    }
     const float grid_size = 12.0;
     vec4 grid_color;
     if ((mod(gl_FragCoord.x, grid_size * 2.0) < grid_size)
@@ -1,11 +1,17 @@
 #version 140
 uniform sampler2D tex;
 uniform float bright_shade;
+uniform bool grayscale;
 uniform float lod_level;
 in vec2 v_tex_coords;
 out vec4 f_color;
 void main() {
     vec4 color = textureLod(tex, v_tex_coords, lod_level);
+    if (grayscale) {
+        // The texture is sRGB so the color is already linear here
+        float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+        color = vec4(vec3(luminance), color.a);
+    }
     const float grid_size = 12.0;
     vec4 grid_color;
     if ((mod(gl_FragCoord.x, grid_size * 2.0) < grid_size)
@@ -0,0 +1,110 @@
+//! Reads the text files that accompany images, like captions in `photo.txt`
+//! or `photo.jpg.txt` next to `photo.jpg`.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::configuration::SidecarConfig;
+
+/// The number of files for which the sidecar contents are kept around.
+const MAX_CACHED_SIDECARS: usize = 64;
+
+/// Sidecars larger than this are ignored unless the config sets `max_bytes`.
+const DEFAULT_MAX_BYTES: u64 = 16 * 1024;
+
+/// The longest text that's shown, longer texts are cut off.
+const MAX_SHOWN_CHARS: usize = 200;
+
+pub struct Sidecars {
+    config: SidecarConfig,
+    /// Image paths in the order they were inserted, oldest first.
+    order: VecDeque<PathBuf>,
+    texts: HashMap<PathBuf, Option<String>>,
+}
+
+impl Sidecars {
+    pub fn new(config: SidecarConfig) -> Sidecars {
+        Sidecars {
+            config,
+            order: VecDeque::new(),
+            texts: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &SidecarConfig {
+        &self.config
+    }
+
+    /// Returns the text of the first sidecar found for the image, collapsed
+    /// onto a single line. The sidecar is read the first time it's asked for.
+    pub fn text(&mut self, image_path: &Path) -> Option<String> {
+        if let Some(text) = self.texts.get(image_path) {
+            return text.clone();
+        }
+        let text = self.read(image_path);
+        if self.order.len() >= MAX_CACHED_SIDECARS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.texts.remove(&oldest);
+            }
+        }
+        self.order.push_back(image_path.to_owned());
+        self.texts.insert(image_path.to_owned(), text.clone());
+        text
+    }
+
+    fn read(&self, image_path: &Path) -> Option<String> {
+        let max_bytes = self.config.max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+        for path in self.candidates(image_path) {
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            if metadata.len() > max_bytes {
+                eprintln!(
+                    "Ignoring the sidecar {:?} because it's larger than {} bytes",
+                    path, max_bytes
+                );
+                continue;
+            }
+            match fs::read(&path) {
+                Ok(contents) => {
+                    let contents = String::from_utf8_lossy(&contents);
+                    let text = single_line(&contents);
+                    if !text.is_empty() {
+                        return Some(text);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Could not read the sidecar {:?}: {}", path, e)
+                }
+            }
+        }
+        None
+    }
+
+    /// `photo.jpg.txt` comes before `photo.txt` for each extension.
+    fn candidates(&self, image_path: &Path) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+        for extension in self.config.extensions.iter() {
+            let extension = extension.trim_start_matches('.');
+            let mut appended = image_path.as_os_str().to_owned();
+            appended.push(".");
+            appended.push(extension);
+            candidates.push(PathBuf::from(appended));
+            candidates.push(image_path.with_extension(extension));
+        }
+        candidates
+    }
+}
+
+fn single_line(text: &str) -> String {
+    let words: Vec<_> = text.split_whitespace().collect();
+    let line = words.join(" ");
+    match line.char_indices().nth(MAX_SHOWN_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line,
+    }
+}
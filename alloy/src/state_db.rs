@@ -0,0 +1,350 @@
+//! Keeps the per-file state of the cache in an SQLite database instead of the
+//! cache file, used with `state_backend = "sqlite"`. Only available with the
+//! `sqlite` feature.
+
+#[cfg(feature = "sqlite")]
+use std::{
+    collections::BTreeMap,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// The name of the database file, which is next to the cache file.
+pub const STATE_DB_FILE_NAME: &str = "state.sqlite";
+
+/// The key in the `meta` table that's set once the maps of the cache file
+/// are copied into the database.
+#[cfg(feature = "sqlite")]
+const MIGRATED_KEY: &str = "migrated_from_cache_file";
+
+/// The values set within this long are written in a single transaction.
+#[cfg(feature = "sqlite")]
+const WRITE_DELAY: Duration = Duration::from_secs(1);
+
+/// The values that are set but not written yet, keyed by the map and the
+/// path. `None` removes the value.
+#[cfg(feature = "sqlite")]
+type PendingWrites = BTreeMap<(String, String), Option<String>>;
+
+/// A handle to the database. Clones share the connection.
+#[derive(Clone)]
+// Without the feature `open` always fails
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub struct StateDb {
+    path: PathBuf,
+    read_only: bool,
+    #[cfg(feature = "sqlite")]
+    conn: Arc<Mutex<rusqlite::Connection>>,
+    #[cfg(feature = "sqlite")]
+    pending: Arc<Mutex<PendingWrites>>,
+    /// Wakes up the thread that writes the pending values.
+    #[cfg(feature = "sqlite")]
+    wake_writer: mpsc::Sender<()>,
+}
+
+impl StateDb {
+    /// Opens the database, creating it if it doesn't exist.
+    ///
+    /// When `read_only` is set, the state is read from the database but
+    /// nothing is written to it, and a missing database is an error.
+    #[cfg(feature = "sqlite")]
+    pub fn open(path: &Path, read_only: bool) -> Result<StateDb, String> {
+        use rusqlite::{Connection, OpenFlags};
+
+        let conn = if read_only {
+            Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        } else {
+            Connection::open(path)
+        }
+        .map_err(|e| e.to_string())?;
+        if !read_only {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS per_file (
+                    map TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    PRIMARY KEY (map, path)
+                ) WITHOUT ROWID;
+                CREATE TABLE IF NOT EXISTS meta (
+                    key TEXT PRIMARY KEY NOT NULL,
+                    value TEXT NOT NULL
+                ) WITHOUT ROWID;",
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        let conn = Arc::new(Mutex::new(conn));
+        let pending = Arc::new(Mutex::new(PendingWrites::new()));
+        let (wake_writer, wake) = mpsc::channel();
+        if !read_only {
+            let conn = conn.clone();
+            let pending = pending.clone();
+            // Stops once every handle is dropped
+            thread::spawn(move || {
+                while wake.recv().is_ok() {
+                    thread::sleep(WRITE_DELAY);
+                    while wake.try_recv().is_ok() {}
+                    write_pending(&conn, &pending);
+                }
+            });
+        }
+        Ok(StateDb {
+            path: path.to_owned(),
+            read_only,
+            conn,
+            pending,
+            wake_writer,
+        })
+    }
+
+    /// Always fails without the `sqlite` feature.
+    #[cfg(not(feature = "sqlite"))]
+    pub fn open(_path: &Path, _read_only: bool) -> Result<StateDb, String> {
+        Err("this build doesn't have the `sqlite` feature".into())
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns true if the maps of the cache file were copied into the
+    /// database, see `set_migrated`.
+    pub fn migrated(&self) -> bool {
+        #[cfg(feature = "sqlite")]
+        {
+            let conn = self.conn.lock().unwrap();
+            // Fails if the table doesn't exist, which a read-only database
+            // may lack
+            conn.query_row(
+                "SELECT 1 FROM meta WHERE key = ?1",
+                [MIGRATED_KEY],
+                |_| Ok(()),
+            )
+            .is_ok()
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            false
+        }
+    }
+
+    /// Records that the maps of the cache file were copied into the
+    /// database, so that they are never copied again.
+    pub fn set_migrated(&self) -> Result<(), String> {
+        if self.read_only {
+            return Ok(());
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, '1')",
+                [MIGRATED_KEY],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            Ok(())
+        }
+    }
+
+    /// Returns the value stored for the file in the map called `map`.
+    pub fn get(&self, map: &str, file: &Path) -> Option<String> {
+        #[cfg(feature = "sqlite")]
+        {
+            use rusqlite::OptionalExtension;
+
+            let key = (map.to_owned(), file.to_string_lossy().into_owned());
+            if let Some(value) = self.pending.lock().unwrap().get(&key) {
+                return value.clone();
+            }
+            let conn = self.conn.lock().unwrap();
+            let result = conn
+                .query_row(
+                    "SELECT value FROM per_file WHERE map = ?1 AND path = ?2",
+                    rusqlite::params![map, file.to_string_lossy()],
+                    |row| row.get(0),
+                )
+                .optional();
+            match result {
+                Ok(value) => value,
+                Err(e) => {
+                    eprintln!(
+                        "Could not read {:?} of {:?} from the state database: {}",
+                        map, file, e
+                    );
+                    None
+                }
+            }
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            let _ = (map, file);
+            None
+        }
+    }
+
+    /// Stores the value for the file, or removes it if `value` is `None`.
+    ///
+    /// The value is written by a background thread along with the other
+    /// values set around the same time, `get` returns it right away.
+    pub fn set(&self, map: &str, file: &Path, value: Option<&str>) {
+        if self.read_only {
+            return;
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            let key = (map.to_owned(), file.to_string_lossy().into_owned());
+            self.pending
+                .lock()
+                .unwrap()
+                .insert(key, value.map(str::to_owned));
+            let _ = self.wake_writer.send(());
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            let _ = (map, file, value);
+        }
+    }
+
+    /// Writes the values that the background thread hasn't written yet.
+    pub fn flush(&self) {
+        #[cfg(feature = "sqlite")]
+        write_pending(&self.conn, &self.pending);
+    }
+
+    /// Stores all of the entries in a single transaction. This is used to
+    /// copy the maps of the cache file into the database.
+    pub fn insert_all<'a, I>(&self, map: &str, entries: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = (&'a str, String)>,
+    {
+        if self.read_only {
+            return Ok(());
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            let mut conn = self.conn.lock().unwrap();
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            {
+                let mut statement = tx
+                    .prepare(
+                        "INSERT OR REPLACE INTO per_file (map, path, value) VALUES (?1, ?2, ?3)",
+                    )
+                    .map_err(|e| e.to_string())?;
+                for (path, value) in entries {
+                    statement
+                        .execute(rusqlite::params![map, path, value])
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            tx.commit().map_err(|e| e.to_string())
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            let _ = (map, entries.into_iter());
+            Ok(())
+        }
+    }
+}
+
+/// Writes the pending values in a single transaction.
+#[cfg(feature = "sqlite")]
+fn write_pending(
+    conn: &Mutex<rusqlite::Connection>,
+    pending: &Mutex<PendingWrites>,
+) {
+    // The connection is locked first, so that `get` either finds the values
+    // in `pending` or waits until they are committed.
+    let mut conn = conn.lock().unwrap();
+    let pending = std::mem::take(&mut *pending.lock().unwrap());
+    if pending.is_empty() {
+        return;
+    }
+    let result = (|| {
+        let tx = conn.transaction()?;
+        for ((map, path), value) in &pending {
+            match value {
+                Some(value) => tx.execute(
+                    "INSERT OR REPLACE INTO per_file (map, path, value) VALUES (?1, ?2, ?3)",
+                    rusqlite::params![map, path, value],
+                )?,
+                None => tx.execute(
+                    "DELETE FROM per_file WHERE map = ?1 AND path = ?2",
+                    rusqlite::params![map, path],
+                )?,
+            };
+        }
+        tx.commit()
+    })();
+    if let Err(e) = result {
+        eprintln!(
+            "Could not write {} values to the state database: {}",
+            pending.len(),
+            e
+        );
+    }
+}
+
+impl fmt::Debug for StateDb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StateDb")
+            .field("path", &self.path)
+            .field("read_only", &self.read_only)
+            .finish()
+    }
+}
+
+impl PartialEq for StateDb {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.read_only == other.read_only
+    }
+}
+impl Eq for StateDb {}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn pending_values_are_read_back() {
+        let path = env::temp_dir()
+            .join(format!("alloy-state-{}.sqlite", process::id()));
+        let _ = fs::remove_file(&path);
+        let file = Path::new("/pictures/a.jpg");
+
+        assert!(StateDb::open(&path, true).is_err());
+        assert!(!path.exists());
+
+        let db = StateDb::open(&path, false).unwrap();
+        assert!(!db.migrated());
+        db.set_migrated().unwrap();
+        assert!(db.migrated());
+        db.set("rotations", file, Some("90"));
+        assert_eq!(db.get("rotations", file), Some("90".into()));
+        db.flush();
+        assert_eq!(db.get("rotations", file), Some("90".into()));
+        db.set("rotations", file, None);
+        assert_eq!(db.get("rotations", file), None);
+        db.set("scroll_positions", file, Some("12"));
+        db.flush();
+        drop(db);
+
+        let db = StateDb::open(&path, true).unwrap();
+        assert!(db.migrated());
+        assert_eq!(db.get("rotations", file), None);
+        assert_eq!(db.get("scroll_positions", file), Some("12".into()));
+        db.set("scroll_positions", file, Some("3"));
+        assert_eq!(db.get("scroll_positions", file), Some("12".into()));
+        drop(db);
+        fs::remove_file(&path).unwrap();
+    }
+}
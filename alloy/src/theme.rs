@@ -0,0 +1,85 @@
+//! Keeps track of the theme so that it can be queried and changed while the
+//! viewer is running.
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    configuration::{Cache, Theme},
+    utils::os_prefers_dark_theme,
+};
+
+type ChangeHandler = Rc<dyn Fn(Theme)>;
+
+pub struct ThemeController {
+    theme: Cell<Theme>,
+    /// The theme that the desktop prefers. Asking the desktop starts a
+    /// process, so this is only done when `Theme::System` is resolved, and
+    /// again after the theme is set.
+    system_theme: Cell<Option<Theme>>,
+    cache: Arc<Mutex<Cache>>,
+    on_change: RefCell<Vec<ChangeHandler>>,
+}
+
+impl ThemeController {
+    pub fn new(theme: Theme, cache: Arc<Mutex<Cache>>) -> ThemeController {
+        ThemeController {
+            theme: Cell::new(theme),
+            system_theme: Cell::new(None),
+            cache,
+            on_change: Default::default(),
+        }
+    }
+
+    /// Returns `Theme::Light` or `Theme::Dark`, using the preference of the
+    /// desktop for `Theme::System`.
+    pub fn resolve(&self, theme: Theme) -> Theme {
+        match theme {
+            Theme::System => match self.system_theme.get() {
+                Some(system_theme) => system_theme,
+                None => {
+                    let system_theme = query_system_theme();
+                    self.system_theme.set(Some(system_theme));
+                    system_theme
+                }
+            },
+            theme => theme,
+        }
+    }
+
+    /// Returns the theme that's shown. `Theme::System` is resolved to the
+    /// preference of the desktop, so this is never `Theme::System`.
+    pub fn current_theme(&self) -> Theme {
+        self.resolve(self.theme.get())
+    }
+
+    /// Shows `theme` and remembers it in the cache, then calls the change
+    /// handlers with the resolved theme.
+    pub fn set_theme_runtime(&self, theme: Theme) {
+        self.theme.set(theme);
+        self.system_theme.set(None);
+        let current = self.current_theme();
+        self.cache.lock().unwrap().set_theme(theme, current);
+        // Cloned so that the handlers may add handlers
+        let handlers = self.on_change.borrow().clone();
+        for handler in handlers {
+            handler(current);
+        }
+    }
+
+    /// Adds a handler that's called whenever the theme is set.
+    pub fn add_on_change<F: Fn(Theme) + 'static>(&self, handler: F) {
+        self.on_change.borrow_mut().push(Rc::new(handler));
+    }
+}
+
+fn query_system_theme() -> Theme {
+    if os_prefers_dark_theme() {
+        Theme::Dark
+    } else {
+        Theme::Light
+    }
+}
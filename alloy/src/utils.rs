@@ -1,4 +1,99 @@
+use std::process::Command;
+
 use gelatin::glium::glutin::event::VirtualKeyCode;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref OS_PREFERS_REDUCED_MOTION: bool = detect_reduced_motion();
+}
+
+/// Returns true if the desktop asks for animations to be turned off. Only
+/// GNOME and desktops following its settings are detected. The desktop is
+/// only asked the first time.
+pub fn os_prefers_reduced_motion() -> bool {
+    *OS_PREFERS_REDUCED_MOTION
+}
+
+fn detect_reduced_motion() -> bool {
+    if !cfg!(all(unix, not(target_os = "macos"))) {
+        return false;
+    }
+    let output = Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "enable-animations"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim() == "false"
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if the desktop asks for a dark theme. Like
+/// `os_prefers_reduced_motion`, only GNOME's setting is detected.
+pub fn os_prefers_dark_theme() -> bool {
+    if !cfg!(all(unix, not(target_os = "macos"))) {
+        return false;
+    }
+    let output = Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "color-scheme"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).contains("prefer-dark")
+        }
+        _ => false,
+    }
+}
+
+/// Scores how well `query` matches `candidate` when its characters appear in
+/// `candidate` in the same order, not necessarily next to each other. Runs of
+/// consecutive characters and matches at the start of words score higher.
+/// Returns `None` if some character of `query` isn't found.
+pub fn fuzzy_score(
+    query: &str,
+    candidate: &str,
+    case_sensitive: bool,
+) -> Option<i64> {
+    let normalize = |ch: char| {
+        if case_sensitive {
+            ch
+        } else {
+            ch.to_lowercase().next().unwrap_or(ch)
+        }
+    };
+    let mut query = query.chars().map(normalize).peekable();
+    let mut score = 0;
+    let mut prev: Option<char> = None;
+    let mut prev_matched = false;
+    let mut first_match = None;
+    for (i, ch) in candidate.chars().enumerate() {
+        let wanted = match query.peek() {
+            Some(&wanted) => wanted,
+            None => break,
+        };
+        let matched = normalize(ch) == wanted;
+        if matched {
+            query.next();
+            score += 1;
+            if prev_matched {
+                score += 5;
+            }
+            if prev.is_none_or(|prev| !prev.is_alphanumeric()) {
+                score += 3;
+            }
+            first_match.get_or_insert(i as i64);
+        }
+        prev_matched = matched;
+        prev = Some(ch);
+    }
+    if query.peek().is_some() {
+        return None;
+    }
+    // Earlier matches and shorter names are better
+    let length = candidate.chars().count() as i64;
+    Some(score * 10 - first_match.unwrap_or(0) - length / 4)
+}
 
 pub fn virtual_keycode_is_char(vk: VirtualKeyCode) -> bool {
     #[allow(clippy::match_like_matches_macro)]
@@ -259,3 +354,36 @@ pub fn virtual_keycode_to_string(vk: VirtualKeyCode) -> String {
         VirtualKeyCode::Cut => "Cut".into(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_score_matches_in_order() {
+        assert!(fuzzy_score("abc", "a_b_c.png", false).is_some());
+        assert_eq!(fuzzy_score("cba", "a_b_c.png", false), None);
+        assert_eq!(fuzzy_score("abcd", "abc.png", false), None);
+        assert!(fuzzy_score("", "abc.png", false).is_some());
+    }
+
+    #[test]
+    fn fuzzy_score_case() {
+        assert!(fuzzy_score("ABC", "abc.png", false).is_some());
+        assert_eq!(fuzzy_score("ABC", "abc.png", true), None);
+        assert!(fuzzy_score("ABC", "ABC.png", true).is_some());
+    }
+
+    #[test]
+    fn fuzzy_score_ranking() {
+        let score = |candidate| fuzzy_score("cat", candidate, false).unwrap();
+        // Consecutive characters
+        assert!(score("cat.png") > score("c_a_t.png"));
+        // Starts of words
+        assert!(score("c_a_t.png") > score("xcxaxt.png"));
+        // Earlier matches
+        assert!(score("cat_1.png") > score("1_cat.png"));
+        // Shorter names
+        assert!(score("cat.png") > score("cat_and_a_long_name.png"));
+    }
+}
@@ -0,0 +1,33 @@
+//! Sets the desktop background, only available with the `wallpaper` feature.
+
+use std::path::Path;
+
+/// Sets the image at `path` as the desktop background.
+///
+/// `mode` is one of "fit", "fill", "stretch" or "center". Not every desktop
+/// supports every mode, so failing to apply the mode only prints a warning.
+pub fn set_wallpaper(path: &Path, mode: Option<&str>) -> Result<(), String> {
+    use wallpaper::Mode;
+
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| format!("The path {:?} is not valid UTF-8", path))?;
+    if let Some(mode) = mode {
+        let mode = match mode {
+            "fit" => Mode::Fit,
+            "fill" => Mode::Crop,
+            "stretch" => Mode::Stretch,
+            "center" => Mode::Center,
+            other => {
+                return Err(format!(
+                    r#"Unknown wallpaper mode {:?}, allowed values are "fit", "fill", "stretch" and "center""#,
+                    other
+                ));
+            }
+        };
+        if let Err(e) = wallpaper::set_mode(mode) {
+            eprintln!("Could not set the wallpaper mode: {}", e);
+        }
+    }
+    wallpaper::set_from_path(path_str).map_err(|e| e.to_string())
+}
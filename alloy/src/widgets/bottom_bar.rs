@@ -1,15 +1,26 @@
-use std::{f32, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    f32,
+    rc::Rc,
+};
 
 use gelatin::{
     button::Button,
+    label::Label,
     line_layout_container::HorizontalLayoutContainer,
     misc::{Alignment, Length},
     picture::Picture,
     slider::Slider,
 };
 
-use super::picture_widget::ScalingMode;
-use crate::{ConfigWindowSection, Configuration, Theme};
+use super::{
+    picture_widget::ScalingMode,
+    text::{render_text, TextStyle, IMAGE_PIXELS_PER_POINT},
+};
+use crate::{
+    configuration::{BarToggle, CacheWindowSection},
+    Configuration,
+};
 
 static MOON: &[u8] = include_bytes!("../../resource/moon.png");
 static LIGHT: &[u8] = include_bytes!("../../resource/light.png");
@@ -36,6 +47,9 @@ const ACTIVE_BG_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 0.5];
 const SMALL_BUTTON_GAP: f32 = 4.0;
 const BIG_BUTTON_GAP: f32 = 32.0;
 const BUTTON_SIZE: f32 = 24.0;
+const INFO_FONT_SIZE: f32 = 13.0;
+const INFO_COLOR_LIGHT: [u8; 3] = [40, 40, 40];
+const INFO_COLOR_DARK: [u8; 3] = [215, 215, 215];
 
 pub struct BottomBar {
     pub widget: Rc<HorizontalLayoutContainer>,
@@ -46,9 +60,21 @@ pub struct BottomBar {
     pub theme_button: Rc<Button>,
     pub help_button: Rc<Button>,
 
-    /// This is false if the configuration requires this to be invisible
-    // and true otherwise.
-    pub should_show: bool,
+    /// The text next to the scaling buttons, rendered again only when it
+    /// changes or the theme changes.
+    info: Rc<Label>,
+    info_text: RefCell<String>,
+    dark: Cell<bool>,
+
+    /// Whether the bar is shown in the windowed mode and in fullscreen, from
+    /// the cache if it was toggled and from the configuration otherwise.
+    show_windowed: Cell<bool>,
+    show_fullscreen: Cell<bool>,
+    fullscreen: Cell<bool>,
+    /// `show_bottom_bar` and `bottom_bar_in_fullscreen`, which the toggled
+    /// states are stored with.
+    configured_windowed: Option<bool>,
+    configured_fullscreen: Option<bool>,
 
     question: Rc<Picture>,
     question_light: Rc<Picture>,
@@ -65,7 +91,11 @@ pub struct BottomBar {
 }
 
 impl BottomBar {
-    pub fn new(config: &Configuration) -> Self {
+    pub fn new(
+        config: &Configuration,
+        cache: &mut CacheWindowSection,
+        fullscreen: bool,
+    ) -> Self {
         let question = Rc::new(Picture::from_encoded_bytes(QUESTION_BUTTON));
         let question_light =
             Rc::new(Picture::from_encoded_bytes(QUESTION_BUTTON_LIGHT));
@@ -95,6 +125,12 @@ impl BottomBar {
         let orig_scale_button = make_icon_button(Alignment::Start);
         let fit_best_button = make_icon_button(Alignment::Start);
         let fit_stretch_button = make_icon_button(Alignment::Start);
+        let info = Rc::new(Label::new());
+        info.set_margin_top(SMALL_BUTTON_GAP);
+        info.set_margin_left(BIG_BUTTON_GAP);
+        info.set_height(Length::Fixed(BUTTON_SIZE));
+        info.set_horizontal_align(Alignment::Start);
+        info.set_visible(false);
         let slider = make_slider();
         let theme_button = make_icon_button(Alignment::End);
         let help_button = make_icon_button(Alignment::End);
@@ -108,20 +144,33 @@ impl BottomBar {
         widget.add_child(orig_scale_button.clone());
         widget.add_child(fit_best_button.clone());
         widget.add_child(fit_stretch_button.clone());
+        widget.add_child(info.clone());
         widget.add_child(slider.clone());
         widget.add_child(theme_button.clone());
         widget.add_child(help_button.clone());
 
-        let should_show = if let Some(ConfigWindowSection {
-            show_bottom_bar: Some(false),
-            ..
-        }) = config.window
-        {
-            widget.set_visible(false);
-            false
+        let window = config.window.as_ref();
+        let configured_windowed = window.and_then(|w| w.show_bottom_bar);
+        let configured_fullscreen =
+            window.and_then(|w| w.bottom_bar_in_fullscreen);
+        let show_windowed = BarToggle::resolve(
+            &mut cache.bottom_bar,
+            configured_windowed,
+            true,
+        );
+        let show_fullscreen = BarToggle::resolve(
+            &mut cache.bottom_bar_in_fullscreen,
+            configured_fullscreen,
+            false,
+        );
+        let visible = if fullscreen {
+            show_fullscreen
         } else {
-            true
+            show_windowed
         };
+        if !visible {
+            widget.set_visible(false);
+        }
 
         Self {
             widget,
@@ -131,7 +180,14 @@ impl BottomBar {
             slider,
             theme_button,
             help_button,
-            should_show,
+            info,
+            info_text: RefCell::new(String::new()),
+            dark: Cell::new(false),
+            show_windowed: Cell::new(show_windowed),
+            show_fullscreen: Cell::new(show_fullscreen),
+            fullscreen: Cell::new(fullscreen),
+            configured_windowed,
+            configured_fullscreen,
 
             question,
             question_light,
@@ -148,49 +204,117 @@ impl BottomBar {
         }
     }
 
-    pub fn set_theme(&self, theme: Theme, update_available: bool) {
-        match theme {
-            Theme::Light => {
-                self.orig_scale_button.set_icon(Some(self.one.clone()));
-                self.fit_best_button.set_icon(Some(self.fit_best.clone()));
-                self.fit_stretch_button
-                    .set_icon(Some(self.fit_stretch.clone()));
-                self.theme_button.set_icon(Some(self.moon_img.clone()));
-                self.widget.set_bg_color([1.0, 1.0, 1.0, 1.0]);
-                self.slider.set_shadow_color([0.0, 0.0, 0.0]);
+    pub fn set_theme(&self, dark: bool, update_available: bool) {
+        if dark != self.dark.get() {
+            self.dark.set(dark);
+            self.render_info();
+        }
+        if dark {
+            self.orig_scale_button
+                .set_icon(Some(self.one_light.clone()));
+            self.fit_best_button
+                .set_icon(Some(self.fit_best_light.clone()));
+            self.fit_stretch_button
+                .set_icon(Some(self.fit_stretch_light.clone()));
+            self.theme_button.set_icon(Some(self.light_img.clone()));
+            self.widget.set_bg_color([0.08, 0.08, 0.08, 1.0]);
+            self.slider.set_shadow_color([0.0, 0.0, 0.0]);
 
-                if update_available {
-                    self.help_button.set_icon(Some(self.question_noti.clone()));
-                } else {
-                    self.help_button.set_icon(Some(self.question.clone()));
-                }
+            if update_available {
+                self.help_button
+                    .set_icon(Some(self.question_light_noti.clone()));
+            } else {
+                self.help_button.set_icon(Some(self.question_light.clone()));
             }
-            Theme::Dark => {
-                self.orig_scale_button
-                    .set_icon(Some(self.one_light.clone()));
-                self.fit_best_button
-                    .set_icon(Some(self.fit_best_light.clone()));
-                self.fit_stretch_button
-                    .set_icon(Some(self.fit_stretch_light.clone()));
-                self.theme_button.set_icon(Some(self.light_img.clone()));
-                self.widget.set_bg_color([0.08, 0.08, 0.08, 1.0]);
-                self.slider.set_shadow_color([0.0, 0.0, 0.0]);
+        } else {
+            self.orig_scale_button.set_icon(Some(self.one.clone()));
+            self.fit_best_button.set_icon(Some(self.fit_best.clone()));
+            self.fit_stretch_button
+                .set_icon(Some(self.fit_stretch.clone()));
+            self.theme_button.set_icon(Some(self.moon_img.clone()));
+            self.widget.set_bg_color([1.0, 1.0, 1.0, 1.0]);
+            self.slider.set_shadow_color([0.0, 0.0, 0.0]);
 
-                if update_available {
-                    self.help_button
-                        .set_icon(Some(self.question_light_noti.clone()));
-                } else {
-                    self.help_button
-                        .set_icon(Some(self.question_light.clone()));
-                }
+            if update_available {
+                self.help_button.set_icon(Some(self.question_noti.clone()));
+            } else {
+                self.help_button.set_icon(Some(self.question.clone()));
             }
         }
     }
 
-    /// Sets this visible iff both the `visible` parameter is `true` and
-    /// the `should_show` property of this object is `true`
-    pub fn set_visible_if_should_show(&self, visible: bool) {
-        self.widget.set_visible(visible && self.should_show);
+    /// Shows or hides the bar according to the setting of the new mode, so
+    /// that leaving fullscreen restores the windowed state.
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        self.fullscreen.set(fullscreen);
+        self.update_visible();
+    }
+
+    /// Toggles the bar in the current mode only, and stores the new state in
+    /// the cache separately for the windowed mode and fullscreen.
+    pub fn toggle(&self, cache: &mut CacheWindowSection) {
+        if self.fullscreen.get() {
+            let show = !self.show_fullscreen.get();
+            self.show_fullscreen.set(show);
+            cache.bottom_bar_in_fullscreen = Some(BarToggle {
+                shown: show,
+                configured: self.configured_fullscreen,
+            });
+        } else {
+            let show = !self.show_windowed.get();
+            self.show_windowed.set(show);
+            cache.bottom_bar = Some(BarToggle {
+                shown: show,
+                configured: self.configured_windowed,
+            });
+        }
+        self.update_visible();
+    }
+
+    fn update_visible(&self) {
+        let visible = if self.fullscreen.get() {
+            self.show_fullscreen.get()
+        } else {
+            self.show_windowed.get()
+        };
+        self.widget.set_visible(visible);
+    }
+
+    /// Shows the text next to the scaling buttons, or nothing if it's empty.
+    pub fn set_info(&self, text: &str) {
+        if *self.info_text.borrow() == text {
+            return;
+        }
+        *self.info_text.borrow_mut() = text.to_owned();
+        self.render_info();
+    }
+
+    fn render_info(&self) {
+        let color = if self.dark.get() {
+            INFO_COLOR_DARK
+        } else {
+            INFO_COLOR_LIGHT
+        };
+        let style = TextStyle {
+            color,
+            background: [0, 0, 0, 0],
+            font_size: INFO_FONT_SIZE,
+            height: BUTTON_SIZE,
+            padding: 0.0,
+        };
+        match render_text(&self.info_text.borrow(), &style) {
+            Some(image) => {
+                let width = image.width() as f32 / IMAGE_PIXELS_PER_POINT;
+                self.info.set_width(Length::Fixed(width));
+                self.info
+                    .set_icon(Some(Rc::new(Picture::from_image(image))));
+                self.info.set_visible(true);
+            }
+            None => {
+                self.info.set_icon(None);
+                self.info.set_visible(false);
+            }
+        }
     }
 
     pub fn set_help_visible(&self, visible: bool) {
@@ -0,0 +1,121 @@
+use std::rc::{Rc, Weak};
+
+use gelatin::{
+    image::{Rgba, RgbaImage},
+    label::Label,
+    misc::*,
+    picture::Picture,
+    Widget,
+};
+
+use crate::{configuration::HistogramMode, image_cache::image_loader::Levels};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 128;
+
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 160]);
+
+/// Draws the histogram as bars. With `HistogramMode::Rgb` each channel is
+/// drawn in its own color so overlapping bars mix.
+fn render(levels: &Levels, mode: HistogramMode, grayscale: bool) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+    let series: Vec<(&[u32; 256], [u8; 3])> = match mode {
+        HistogramMode::Rgb if !grayscale => vec![
+            (&levels.channels[0], [255, 0, 0]),
+            (&levels.channels[1], [0, 255, 0]),
+            (&levels.channels[2], [0, 0, 255]),
+        ],
+        _ => vec![(&levels.luminance, [255, 255, 255])],
+    };
+    let max = series
+        .iter()
+        .flat_map(|(levels, _)| levels.iter())
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    for (levels, color) in series {
+        for (x, &count) in levels.iter().enumerate() {
+            let bar_h = (count as u64 * HEIGHT as u64 / max as u64) as u32;
+            for y in HEIGHT - bar_h..HEIGHT {
+                let pixel = image.get_pixel_mut(x as u32, y);
+                if pixel.0 == BACKGROUND.0 {
+                    pixel.0 = [0, 0, 0, 255];
+                }
+                for (c, value) in color.iter().enumerate() {
+                    pixel.0[c] = pixel.0[c].max(*value);
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Shows the histogram of the current image over the picture, toggled by
+/// `toggle_histogram`.
+pub struct Histogram {
+    pub widget: Weak<Label>,
+    enabled: bool,
+    /// What the shown histogram was made from, so that it's only computed
+    /// again when one of these changes.
+    source: Option<(Weak<Levels>, bool, HistogramMode)>,
+}
+
+impl Histogram {
+    pub fn new(widget: &Rc<Label>) -> Histogram {
+        widget.set_icon(None);
+        widget.set_ignore_layout(true);
+        widget.set_width(Length::Fixed(WIDTH as f32));
+        widget.set_height(Length::Fixed(HEIGHT as f32));
+        widget.set_margin_all(4.0);
+        widget.set_horizontal_align(Alignment::End);
+        widget.set_vertical_align(Alignment::Start);
+        widget.set_visible(false);
+
+        Histogram {
+            widget: Rc::downgrade(widget),
+            enabled: false,
+            source: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Computes the histogram again if the image, the grayscale setting or
+    /// the mode changed.
+    pub fn update(
+        &mut self,
+        levels: Option<Rc<Levels>>,
+        grayscale: bool,
+        mode: HistogramMode,
+    ) {
+        let widget = self.widget.upgrade().unwrap();
+        let levels = match levels {
+            Some(levels) if self.enabled => levels,
+            _ => {
+                if widget.visible() {
+                    widget.set_visible(false);
+                }
+                self.source = None;
+                return;
+            }
+        };
+        let up_to_date = match self.source {
+            Some((ref prev_levels, prev_grayscale, prev_mode)) => {
+                prev_levels.ptr_eq(&Rc::downgrade(&levels))
+                    && prev_grayscale == grayscale
+                    && prev_mode == mode
+            }
+            None => false,
+        };
+        if up_to_date {
+            return;
+        }
+        self.source = Some((Rc::downgrade(&levels), grayscale, mode));
+        let image = render(&levels, mode, grayscale);
+        widget.set_icon(Some(Rc::new(Picture::from_image(image))));
+        widget.set_visible(true);
+    }
+}
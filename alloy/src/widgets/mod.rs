@@ -1,4 +1,8 @@
 pub mod bottom_bar;
 pub mod copy_notification;
 pub mod help_screen;
+pub mod histogram;
 pub mod picture_widget;
+pub mod progress_overlay;
+pub mod sidecar_overlay;
+pub mod text;
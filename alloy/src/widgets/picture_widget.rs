@@ -1,6 +1,8 @@
 use std::{
     cell::{Ref, RefCell},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
     rc::{Rc, Weak},
     sync::{Arc, Mutex},
     time::{Duration, Instant},
@@ -8,10 +10,12 @@ use std::{
 
 use gelatin::{
     add_common_widget_functions,
-    application::request_exit,
+    application::{exit_requested, request_exit},
     cgmath::{Matrix4, Vector2, Vector3},
     glium::{
-        glutin::event::{ElementState, ModifiersState, MouseButton},
+        glutin::event::{
+            ElementState, ModifiersState, MouseButton, VirtualKeyCode,
+        },
         program, uniform,
         uniforms::MagnifySamplerFilter,
         Display, Frame, Program, Surface,
@@ -23,21 +27,51 @@ use gelatin::{
 
 use super::{
     bottom_bar::BottomBar, copy_notification::CopyNotifications,
-    help_screen::HelpScreen,
+    help_screen::HelpScreen, histogram::Histogram,
+    progress_overlay::ProgressOverlay, sidecar_overlay::SidecarOverlay,
 };
 use crate::{
     clipboard_handler::ClipboardHandler,
-    configuration::{Antialias, Cache, Configuration},
-    image_cache::{image_loader::Orientation, AnimationFrameTexture},
+    configuration::{
+        Antialias, Cache, Configuration, DropBehavior, FitMode, PasteTarget,
+        ReopenSame, SidecarLocation, DEFAULT_BOTTOM_BAR_SEPARATOR,
+        DEFAULT_MAPS_URL_TEMPLATE, DEFAULT_RESIZE_DEBOUNCE_MS,
+        DEFAULT_SIZE_PRECISION, MAX_RESIZE_DEBOUNCE_MS,
+    },
+    event_hooks::EventHooks,
+    image_cache::{
+        filter::ImageFilter,
+        image_loader::{DecodeSettings, Orientation},
+        thumbnailer::Thumbnailer,
+        AnimationFrameTexture,
+    },
     input_handling::*,
+    metadata::{format_metadata, gps_coordinates},
     playback_manager::*,
     shaders,
-    utils::{virtual_keycode_is_char, virtual_keycode_to_string},
+    sidecar::Sidecars,
+    utils::{
+        fuzzy_score, os_prefers_reduced_motion, virtual_keycode_is_char,
+        virtual_keycode_to_string,
+    },
 };
 
 const MIN_ZOOM_FACTOR: f32 = 0.0001;
 const MAX_ZOOM_FACTOR: f32 = 10000.0;
 const AA_TEXEL_SIZE_THRESHOLD: f32 = 4f32;
+/// How far a pan key moves the image at once with `reduce_motion`, in
+/// logical pixels
+const REDUCED_MOTION_PAN_STEP: f32 = 64.0;
+/// How much a zoom key zooms at once with `reduce_motion`
+const REDUCED_MOTION_ZOOM_STEP: f32 = 0.25;
+/// How long a status message is shown in the bottom bar
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(3);
+/// How long an image decoded with `jpeg_decode_scale` is shown before it's
+/// decoded again at full quality
+const QUALITY_SETTLE_DELAY: Duration = Duration::from_millis(300);
+/// Each item of a multi-item drop arrives as a separate event. Drops closer
+/// to each other than this are considered to be part of the same one.
+const DROP_BATCH_INTERVAL: Duration = Duration::from_millis(200);
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ScalingMode {
@@ -46,6 +80,14 @@ pub enum ScalingMode {
     FitMin,
 }
 
+/// A movement of the camera when `reduce_motion` is on, in the directions
+/// that the pan and zoom velocities move it.
+#[derive(Clone, Copy)]
+enum CameraStep {
+    Zoom(f32),
+    Pan(f32, f32),
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum MovementDir {
     None,
@@ -59,6 +101,90 @@ impl MovementDir {
     }
 }
 
+/// A short message reporting the outcome of an action, displayed in the
+/// bottom bar until `until`.
+struct StatusMessage {
+    text: String,
+    until: Instant,
+}
+
+/// The name typed after `goto_file`, shown in the window title while it's
+/// typed.
+struct GotoFile {
+    query: String,
+    /// The index and the name of the file that matches the query best
+    best: Option<(usize, String)>,
+}
+
+/// What runs the actions in `PictureWidget::run_actions`
+#[derive(Clone, Copy)]
+enum ActionTrigger<'a> {
+    /// A key press, which runs the actions bound to it
+    Key {
+        input_key: &'a str,
+        modifiers: ModifiersState,
+    },
+    /// A single action given by its name
+    Action(&'a str),
+}
+impl<'a> ActionTrigger<'a> {
+    fn triggers(
+        self,
+        config: &Rc<RefCell<Configuration>>,
+        action_name: &str,
+    ) -> bool {
+        match self {
+            ActionTrigger::Key {
+                input_key,
+                modifiers,
+            } => action_triggered(config, action_name, input_key, modifiers),
+            ActionTrigger::Action(name) => name == action_name,
+        }
+    }
+
+    /// Returns the parameters of the triggered actions that start with
+    /// `prefix`, like `keep` for `tag:keep`.
+    fn parameters(
+        self,
+        config: &Rc<RefCell<Configuration>>,
+        prefix: &str,
+    ) -> Vec<String> {
+        let parameter = |action: &str| {
+            action
+                .strip_prefix(prefix)
+                .filter(|p| !p.is_empty())
+                .map(str::to_owned)
+        };
+        match self {
+            ActionTrigger::Key {
+                input_key,
+                modifiers,
+            } => {
+                let config = config.borrow();
+                config
+                    .bindings
+                    .iter()
+                    .flatten()
+                    .filter(|(_, keys)| {
+                        keys_triggered(keys.as_slice(), input_key, modifiers)
+                    })
+                    .filter_map(|(action, _)| parameter(action))
+                    .collect()
+            }
+            ActionTrigger::Action(name) => {
+                parameter(name).into_iter().collect()
+            }
+        }
+    }
+}
+
+/// A slideshow that was paused by `slideshow_pause_on_input`
+struct PausedSlideshow {
+    /// Either `Present` or `RandomPresent`
+    state: PlaybackState,
+    last_input: Instant,
+}
+
 #[derive(Debug, Clone)]
 enum HoverState {
     None,
@@ -119,6 +245,13 @@ struct PictureWidgetData {
     placement: WidgetPlacement,
     drawn_bounds: LogicalRect,
     prev_draw_size: LogicalVector,
+    /// When the window was last resized, the image is fitted again at this
+    /// time
+    resize_settles_at: Option<Instant>,
+    /// The file that's shown, and when it's decoded again at full quality if
+    /// it was decoded at a smaller size.
+    quality_path: Option<PathBuf>,
+    quality_settles_at: Instant,
     visible: bool,
     render_validity: RenderValidity,
 
@@ -131,6 +264,8 @@ struct PictureWidgetData {
     // It's an option to allow manual destruction.
     clipboard_handler: Option<ClipboardHandler>,
     clipboard_request_was_pending: bool,
+    /// The clipboard thread is saving the image of `paste_image`
+    paste_pending: bool,
 
     program: Program,
     bright_shade: f32,
@@ -139,6 +274,7 @@ struct PictureWidgetData {
     scaling: ScalingMode,
     img_pos: LogicalVector,
     antialiasing: Antialias,
+    grayscale: bool,
 
     hor_pan_input: MovementDir,
     ver_pan_input: MovementDir,
@@ -154,6 +290,19 @@ struct PictureWidgetData {
     last_mouse_pos: LogicalVector,
     panning: bool,
     hover_state: HoverState,
+    last_drop_time: Instant,
+    /// The file of which the scroll position is remembered.
+    scroll_path: Option<PathBuf>,
+
+    /// Clockwise quarter turns on top of the orientation of the image.
+    rotation: u32,
+    /// The file that `rotation` belongs to.
+    rotation_path: Option<PathBuf>,
+    /// The file of which the fit mode is remembered.
+    fit_path: Option<PathBuf>,
+    /// The remembered fit mode of `fit_path` was applied, so the global mode
+    /// is restored when another file is shown.
+    fit_overridden: bool,
 
     first_draw: bool,
     last_cam_move_time: Instant,
@@ -161,6 +310,27 @@ struct PictureWidgetData {
     bottom_bar: Rc<BottomBar>,
     left_to_pan_hint: Rc<HelpScreen>,
     copy_notifications: CopyNotifications,
+    progress_overlay: ProgressOverlay,
+    sidecar_overlay: SidecarOverlay,
+    histogram: Histogram,
+    status_message: Option<StatusMessage>,
+    goto_file: Option<GotoFile>,
+    paused_slideshow: Option<PausedSlideshow>,
+    reduce_motion: bool,
+    sidecars: Option<Sidecars>,
+    /// The image that `sidecar_text` belongs to.
+    sidecar_path: Option<PathBuf>,
+    /// The file that `file_size` belongs to
+    file_size_path: Option<PathBuf>,
+    file_size: Option<u64>,
+    sidecar_text: Option<String>,
+    /// A copy of the tags of the cache, so that the cache doesn't need to be
+    /// locked every frame. Updated whenever a tag changes.
+    tags: BTreeMap<String, BTreeSet<String>>,
+    event_hooks: EventHooks,
+    /// Selects the next palette and returns its name, or `None` if there
+    /// are no palettes.
+    on_cycle_palette: Option<Rc<dyn Fn() -> Option<String>>>,
     window: Weak<Window>,
 }
 impl WidgetData for PictureWidgetData {
@@ -243,16 +413,56 @@ impl PictureWidgetData {
                 self.img_pos += center_offset;
                 self.apply_img_bounds(dpi_scale);
             }
-            ScalingMode::FitStretch => {
-                self.fit_image_to_panel(dpi_scale, true);
-            }
-            ScalingMode::FitMin => {
-                self.fit_image_to_panel(dpi_scale, false);
+            ScalingMode::FitStretch | ScalingMode::FitMin => {
+                let stretch = self.scaling == ScalingMode::FitStretch;
+                let now = Instant::now();
+                let old_size = self.prev_draw_size.vec;
+                let resized = self.drawn_bounds.size.vec != old_size;
+                if resized && old_size.x > 0.0 && old_size.y > 0.0 {
+                    let debounce = self.resize_debounce();
+                    if debounce > Duration::ZERO {
+                        self.resize_settles_at = Some(now + debounce);
+                    }
+                }
+                match self.resize_settles_at {
+                    Some(at) if at > now => {
+                        if resized {
+                            self.stretch_to_resized_panel(old_size);
+                        }
+                    }
+                    _ => {
+                        self.resize_settles_at = None;
+                        self.fit_image_to_panel(dpi_scale, stretch);
+                    }
+                }
             }
         }
         self.prev_draw_size = self.drawn_bounds.size;
     }
 
+    fn resize_debounce(&self) -> Duration {
+        let ms = self
+            .configuration
+            .borrow()
+            .window
+            .as_ref()
+            .and_then(|w| w.resize_debounce_ms)
+            .unwrap_or(DEFAULT_RESIZE_DEBOUNCE_MS)
+            .min(MAX_RESIZE_DEBOUNCE_MS);
+        Duration::from_millis(ms)
+    }
+
+    /// A cheap stand-in for fitting while the window is being resized: the
+    /// image is scaled around the center as much as the panel was.
+    fn stretch_to_resized_panel(&mut self, old_size: Vector2<f32>) {
+        let size = self.drawn_bounds.size.vec;
+        let ratio = (size.x / old_size.x).min(size.y / old_size.y);
+        let old_center = old_size * 0.5;
+        let center = size * 0.5;
+        self.img_pos.vec = center + (self.img_pos.vec - old_center) * ratio;
+        self.img_texel_size *= ratio;
+    }
+
     fn apply_camera_movement(&mut self, dpi_scale: f32) {
         fn animate_value(
             v: &mut f32,
@@ -334,6 +544,29 @@ impl PictureWidgetData {
         }
     }
 
+    /// Moves the camera by a fixed amount at once, used instead of the
+    /// accelerating movement when `reduce_motion` is on. Holding the key
+    /// repeats the step.
+    fn step_camera(&mut self, step: CameraStep) {
+        match step {
+            CameraStep::Zoom(dir) => {
+                let bounds_size = self.drawn_bounds.size.vec;
+                let anchor = LogicalVector::new(
+                    bounds_size.x * 0.5,
+                    bounds_size.y * 0.5,
+                );
+                self.zoom_image(anchor, dir * REDUCED_MOTION_ZOOM_STEP);
+            }
+            CameraStep::Pan(x, y) => {
+                self.img_pos.vec +=
+                    Vector2::new(x, y) * REDUCED_MOTION_PAN_STEP;
+                self.scaling = ScalingMode::Fixed;
+                self.update_scaling_buttons();
+                self.render_validity.invalidate();
+            }
+        }
+    }
+
     fn camera_movement_will_start(&mut self) {
         // If there hasn't been any movement in a while, then reset the last update time
         // to avoid large jumps at the beggining of a move when the delta would be large.
@@ -365,20 +598,813 @@ impl PictureWidgetData {
             Some(file_path) => title_config.format_file_path(file_path),
             None => "[ none ]".into(),
         };
+        let status = match self.goto_file {
+            Some(ref goto) => match goto.best {
+                Some((_, ref name)) => {
+                    format!(" : Go to: {} \u{2192} {}", goto.query, name)
+                }
+                None if goto.query.is_empty() => {
+                    " : Go to: type a part of the file name".into()
+                }
+                None => format!(" : Go to: {} \u{2192} no match", goto.query),
+            },
+            None => String::new(),
+        };
         let title = format!(
-            "{}{}{}",
+            "{}{}{}{}",
             name,
             playback,
+            status,
             title_config.format_program_name()
         );
         window.set_title(title);
     }
 
+    /// Shows the `bottom_bar_fields`, the tags of the image, whether it's
+    /// downscaled and the status message in the bottom bar.
+    fn update_bottom_bar_info(&self, position: Option<(usize, usize)>) {
+        let config = self.configuration.borrow();
+        let window_config = config.window.as_ref();
+        let mut parts = match window_config {
+            Some(window_config)
+                if window_config.bottom_bar_fields.is_some() =>
+            {
+                let (index, count) = match position {
+                    Some((index, count)) => {
+                        ((index + 1).to_string(), count.to_string())
+                    }
+                    None => ("?".into(), "?".into()),
+                };
+                let (width, height) = match self.get_texture() {
+                    Some(texture) => {
+                        let (w, h) = texture.oriented_dimensions();
+                        (w.to_string(), h.to_string())
+                    }
+                    None => ("?".into(), "?".into()),
+                };
+                let zoom = format!("{:.0}", self.img_texel_size * 100.0);
+                let size = match self.file_size {
+                    Some(bytes) => {
+                        window_config.size_unit.unwrap_or_default().format(
+                            bytes,
+                            window_config
+                                .size_precision
+                                .unwrap_or(DEFAULT_SIZE_PRECISION),
+                        )
+                    }
+                    None => "?".into(),
+                };
+                let mut values = HashMap::with_capacity(6);
+                values.insert("${index}", index.as_str());
+                values.insert("${count}", count.as_str());
+                values.insert("${width}", width.as_str());
+                values.insert("${height}", height.as_str());
+                values.insert("${zoom}", zoom.as_str());
+                values.insert("${size}", size.as_str());
+                window_config.format_bottom_bar_fields(&values)
+            }
+            _ => Vec::new(),
+        };
+        if let Some(path) = self.playback_manager.shown_file_path() {
+            let key = path.to_string_lossy();
+            let tags: Vec<&str> = self
+                .tags
+                .iter()
+                .filter(|(_, files)| files.contains(key.as_ref()))
+                .map(|(tag, _)| tag.as_str())
+                .collect();
+            if !tags.is_empty() {
+                parts.push(format!("[{}]", tags.join(", ")));
+            }
+        }
+        if self.playback_manager.current_downscaled() {
+            parts.push("Downscaled".into());
+        }
+        if let Some(text) = self.sidecar_text(SidecarLocation::BottomBar) {
+            parts.push(text.to_owned());
+        }
+        if let Some(ref message) = self.status_message {
+            parts.push(message.text.clone());
+        }
+        parts.retain(|part| !part.is_empty());
+        let separator = window_config
+            .map(|w| w.bottom_bar_separator())
+            .unwrap_or(DEFAULT_BOTTOM_BAR_SEPARATOR);
+        self.bottom_bar.set_info(&parts.join(separator));
+    }
+
+    /// Returns the topmost visible row of the image, or `None` if the image
+    /// isn't taller than the widget.
+    fn top_visible_row(&self, dpi_scale: f32) -> Option<u32> {
+        let texture = self.get_texture()?;
+        let (_, h) = texture.oriented_dimensions();
+        let img_h = h as f32 * self.img_texel_size / dpi_scale;
+        if img_h <= self.drawn_bounds.size.vec.y {
+            return None;
+        }
+        let img_top = self.img_pos.vec.y - img_h / 2.0;
+        let row = -img_top * dpi_scale / self.img_texel_size;
+        Some(row.max(0.0).min(h as f32) as u32)
+    }
+
+    fn set_top_visible_row(&mut self, row: u32, dpi_scale: f32) {
+        if let Some(texture) = self.get_texture() {
+            let (_, h) = texture.oriented_dimensions();
+            let img_h = h as f32 * self.img_texel_size / dpi_scale;
+            if img_h <= self.drawn_bounds.size.vec.y {
+                return;
+            }
+            let img_top = -(row as f32) * self.img_texel_size / dpi_scale;
+            self.img_pos.vec.y = img_top + img_h / 2.0;
+            self.apply_img_bounds(dpi_scale);
+        }
+    }
+
+    /// Saves the scroll position of the shown image into the cache, or
+    /// restores it if the image was just opened.
+    fn update_remembered_scroll(&mut self, dpi_scale: f32) {
+        let remember = self
+            .configuration
+            .borrow()
+            .image
+            .as_ref()
+            .and_then(|s| s.remember_scroll)
+            .unwrap_or(false);
+        if !remember {
+            return;
+        }
+        let path = self.playback_manager.shown_file_path();
+        if *path != self.scroll_path {
+            self.scroll_path = path.clone();
+            let saved = self.scroll_path.as_ref().and_then(|path| {
+                self.cache.lock().unwrap().image.scroll_position(path)
+            });
+            if let Some(row) = saved {
+                self.set_top_visible_row(row, dpi_scale);
+            }
+        } else if let Some(ref path) = self.scroll_path {
+            if let Some(row) = self.top_visible_row(dpi_scale) {
+                let mut cache = self.cache.lock().unwrap();
+                if cache.image.scroll_position(path) != Some(row) {
+                    cache.image.set_scroll_position(path, row);
+                }
+            }
+        }
+    }
+
+    /// Opens the file or folder, taking `reopen_same` into account if it's
+    /// the file that's shown already.
+    fn open_path(&mut self, path: PathBuf) {
+        let shown = self.playback_manager.shown_file_path();
+        if shown.as_ref().is_some_and(|shown| same_file(shown, &path)) {
+            let reopen_same = self
+                .configuration
+                .borrow()
+                .image
+                .as_ref()
+                .and_then(|s| s.reopen_same)
+                .unwrap_or_default();
+            match reopen_same {
+                ReopenSame::Keep => {}
+                ReopenSame::Reset => {
+                    let stretch =
+                        self.cache.lock().unwrap().image.fit_stretches;
+                    self.set_img_size_to_fit(stretch);
+                }
+                ReopenSame::Reload => self.playback_manager.reload_current(),
+            }
+            return;
+        }
+        self.playback_manager
+            .request_load(LoadRequest::FilePath(path));
+    }
+
+    fn drop_behavior(&self) -> DropBehavior {
+        self.configuration
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.drop_behavior)
+            .unwrap_or_default()
+    }
+
+    fn handle_dropped_file(&mut self, path: &Path) {
+        let now = Instant::now();
+        let first_dropped =
+            now.duration_since(self.last_drop_time) > DROP_BATCH_INTERVAL;
+        self.last_drop_time = now;
+        let nothing_open = self.playback_manager.shown_file_path().is_none();
+        let behavior = self.drop_behavior();
+        let path = match behavior {
+            DropBehavior::OpenFolder if path.is_dir() => path.to_owned(),
+            DropBehavior::OpenFolder => match path.parent() {
+                Some(parent) => parent.to_owned(),
+                None => return,
+            },
+            _ => path.to_owned(),
+        };
+        let append = match behavior {
+            DropBehavior::ReplacePlaylist => !first_dropped,
+            DropBehavior::AppendPlaylist => !first_dropped || !nothing_open,
+            DropBehavior::OpenFolder => {
+                if !first_dropped {
+                    // Only one folder can be open
+                    return;
+                }
+                false
+            }
+        };
+        if append {
+            self.playback_manager.append(path);
+        } else {
+            self.playback_manager.clear_appended();
+            if let HoverState::ItemHovered { .. } = self.hover_state {
+                // The item is already shown as a preview.
+                self.playback_manager
+                    .request_load(LoadRequest::FilePath(path));
+            } else {
+                self.open_path(path);
+            }
+        }
+    }
+
+    /// Pauses a running slideshow if `slideshow_pause_on_input` is set.
+    fn pause_slideshow_on_input(&mut self) {
+        let pause_on_input = self
+            .configuration
+            .borrow()
+            .playback
+            .as_ref()
+            .and_then(|p| p.slideshow_pause_on_input)
+            .unwrap_or(false);
+        if !pause_on_input {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(ref mut paused) = self.paused_slideshow {
+            paused.last_input = now;
+            return;
+        }
+        let state = self.playback_manager.playback_state();
+        if let PlaybackState::Present | PlaybackState::RandomPresent = state {
+            self.playback_manager.pause_playback();
+            self.paused_slideshow = Some(PausedSlideshow {
+                state,
+                last_input: now,
+            });
+            self.show_status("Slideshow paused");
+        }
+    }
+
+    /// Loads the image again at full quality once it has been shown for
+    /// `QUALITY_SETTLE_DELAY`, if it was decoded with `jpeg_decode_scale`.
+    fn update_reduced_quality(&mut self, now: Instant) {
+        let path = self.playback_manager.shown_file_path();
+        if *path != self.quality_path {
+            self.quality_path = path.clone();
+            self.quality_settles_at = now + QUALITY_SETTLE_DELAY;
+        }
+        if !self.playback_manager.current_reduced_quality() {
+            return;
+        }
+        if now >= self.quality_settles_at {
+            self.playback_manager.load_full_quality();
+        } else {
+            self.next_update = self
+                .next_update
+                .aggregate(NextUpdate::WaitUntil(self.quality_settles_at));
+        }
+    }
+
+    fn resume_slideshow(&mut self) {
+        if let Some(paused) = self.paused_slideshow.take() {
+            match paused.state {
+                PlaybackState::Present => {
+                    self.playback_manager.start_presentation()
+                }
+                PlaybackState::RandomPresent => {
+                    self.playback_manager.start_random_presentation()
+                }
+                _ => {}
+            }
+            self.render_validity.invalidate();
+        }
+    }
+
+    /// Resumes the paused slideshow once the user has been idle for
+    /// `slideshow_resume_secs`.
+    fn update_paused_slideshow(&mut self) {
+        let last_input = match self.paused_slideshow {
+            Some(ref paused) => paused.last_input,
+            None => return,
+        };
+        if self.playback_manager.playback_state() != PlaybackState::Paused {
+            // The slideshow was restarted or something else is playing.
+            self.paused_slideshow = None;
+            return;
+        }
+        let resume_secs = self
+            .configuration
+            .borrow()
+            .playback
+            .as_ref()
+            .and_then(|p| p.slideshow_resume_secs);
+        if let Some(resume_secs) = resume_secs {
+            let resume_at = last_input + Duration::from_secs(resume_secs);
+            if Instant::now() >= resume_at {
+                self.resume_slideshow();
+                self.next_update = NextUpdate::Soonest;
+            } else {
+                self.next_update = self
+                    .next_update
+                    .aggregate(NextUpdate::WaitUntil(resume_at));
+            }
+        }
+    }
+
+    /// Reads the size of the shown file when another file is shown.
+    fn update_file_size(&mut self) {
+        let path = self.playback_manager.shown_file_path();
+        if *path == self.file_size_path {
+            return;
+        }
+        self.file_size_path = path.clone();
+        self.file_size = self
+            .file_size_path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len());
+    }
+
+    /// Reads the sidecar of the shown image when another image is shown.
+    fn update_sidecar(&mut self) {
+        let path = self.playback_manager.shown_file_path();
+        if *path == self.sidecar_path {
+            return;
+        }
+        self.sidecar_path = path.clone();
+        let sidecars = match self.sidecars {
+            Some(ref mut sidecars) => sidecars,
+            None => return,
+        };
+        self.sidecar_text = self
+            .sidecar_path
+            .as_ref()
+            .and_then(|path| sidecars.text(path));
+        let overlay_text = self.sidecar_text(SidecarLocation::Overlay);
+        let overlay_text = overlay_text.map(str::to_owned);
+        self.sidecar_overlay.set_text(overlay_text.as_deref());
+    }
+
+    /// Returns the sidecar text of the shown image if it's shown at
+    /// `location`.
+    fn sidecar_text(&self, location: SidecarLocation) -> Option<&str> {
+        let sidecars = self.sidecars.as_ref()?;
+        if sidecars.config().location.unwrap_or_default() == location {
+            self.sidecar_text.as_deref()
+        } else {
+            None
+        }
+    }
+
+    fn show_status<S: Into<String>>(&mut self, text: S) {
+        self.status_message = Some(StatusMessage {
+            text: text.into(),
+            until: Instant::now() + STATUS_MESSAGE_DURATION,
+        });
+    }
+
+    #[cfg(feature = "wallpaper")]
+    fn set_wallpaper(&mut self) {
+        let path = match self.playback_manager.shown_file_path() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let mode = self
+            .configuration
+            .borrow()
+            .image
+            .as_ref()
+            .and_then(|s| s.wallpaper_mode.clone());
+        match crate::wallpaper::set_wallpaper(&path, mode.as_deref()) {
+            Ok(()) => self.show_status("Wallpaper set"),
+            Err(e) => {
+                eprintln!("Could not set {:?} as the wallpaper: {}", path, e);
+                self.show_status("Could not set the wallpaper");
+            }
+        }
+    }
+
     fn get_texture(&self) -> Option<AnimationFrameTexture> {
-        self.playback_manager.image_texture()
+        let mut texture = self.playback_manager.image_texture()?;
+        texture.orientation = texture.orientation.rotated_cw(self.rotation);
+        Some(texture)
+    }
+
+    fn persist_rotation(&self) -> bool {
+        self.configuration
+            .borrow()
+            .image
+            .as_ref()
+            .and_then(|s| s.persist_rotation)
+            .unwrap_or(false)
+    }
+
+    fn remember_fit_per_file(&self) -> bool {
+        self.configuration
+            .borrow()
+            .image
+            .as_ref()
+            .and_then(|s| s.remember_fit_per_file)
+            .unwrap_or(false)
+    }
+
+    /// The fit mode that files without a remembered one are shown with.
+    fn global_fit_mode(&self) -> FitMode {
+        if self.cache.lock().unwrap().image.fit_stretches {
+            FitMode::Fit
+        } else {
+            FitMode::FitBest
+        }
+    }
+
+    fn apply_fit_mode(&mut self, mode: FitMode) {
+        match mode {
+            FitMode::Original => {
+                self.img_texel_size = 1.0;
+                self.scaling = ScalingMode::Fixed;
+            }
+            FitMode::Fit => self.scaling = ScalingMode::FitStretch,
+            FitMode::FitBest => self.scaling = ScalingMode::FitMin,
+        }
+        self.update_scaling_buttons();
+        self.render_validity.invalidate();
+    }
+
+    /// Applies the remembered fit mode when another file is shown, or the
+    /// global one if the previous file had its own.
+    fn update_fit_override(&mut self) {
+        let path = self.playback_manager.shown_file_path();
+        if *path == self.fit_path {
+            return;
+        }
+        self.fit_path = path.clone();
+        if !self.remember_fit_per_file() {
+            return;
+        }
+        let saved = self
+            .fit_path
+            .as_ref()
+            .and_then(|path| self.cache.lock().unwrap().image.fit_mode(path));
+        if let Some(mode) = saved {
+            self.apply_fit_mode(mode);
+        } else if self.fit_overridden {
+            self.apply_fit_mode(self.global_fit_mode());
+        }
+        self.fit_overridden = saved.is_some();
+    }
+
+    /// Remembers the fit mode for the shown file with
+    /// `remember_fit_per_file`. Returns true if it was remembered.
+    fn store_fit_mode(&mut self, mode: FitMode) -> bool {
+        if !self.remember_fit_per_file() {
+            return false;
+        }
+        self.update_fit_override();
+        if let Some(ref path) = self.fit_path {
+            self.cache
+                .lock()
+                .unwrap()
+                .image
+                .set_fit_mode(path, Some(mode));
+            self.fit_overridden = true;
+        }
+        self.fit_overridden
+    }
+
+    pub fn reset_fit_override(&mut self) {
+        self.update_fit_override();
+        if let Some(path) = self.fit_path.clone() {
+            self.cache.lock().unwrap().image.set_fit_mode(&path, None);
+            self.fit_overridden = false;
+            self.apply_fit_mode(self.global_fit_mode());
+            self.show_status("Using the global fit mode");
+        }
+    }
+
+    /// Resets the rotation to the default of the folder, or restores the
+    /// remembered one, when another file is shown.
+    fn update_rotation(&mut self) {
+        let path = self.playback_manager.shown_file_path();
+        if *path == self.rotation_path {
+            return;
+        }
+        self.rotation_path = path.clone();
+        let persist = self.persist_rotation();
+        self.rotation = match self.rotation_path {
+            Some(ref path) => {
+                let cache = self.cache.lock().unwrap();
+                let degrees = if persist {
+                    cache.image.rotation(path)
+                } else {
+                    cache.image.folder_rotation_of(path)
+                };
+                degrees / 90 % 4
+            }
+            None => 0,
+        };
+    }
+
+    /// Makes the rotation of the shown image the default for the images of
+    /// its folder.
+    fn set_folder_rotation(&mut self) {
+        self.update_rotation();
+        let folder = match self.rotation_path {
+            Some(ref path) => match path.parent() {
+                Some(folder) => folder.to_owned(),
+                None => return,
+            },
+            None => return,
+        };
+        let degrees = self.rotation * 90;
+        self.cache
+            .lock()
+            .unwrap()
+            .image
+            .set_folder_rotation(&folder, degrees);
+        if degrees == 0 {
+            self.show_status("Removed the rotation of this folder");
+        } else {
+            self.show_status(format!(
+                "Images in this folder are rotated by {}°",
+                degrees
+            ));
+        }
+    }
+
+    fn rotate(&mut self, cw_quarter_turns: u32) {
+        self.update_rotation();
+        if self.rotation_path.is_none() {
+            return;
+        }
+        self.rotation = (self.rotation + cw_quarter_turns) % 4;
+        let degrees = self.rotation * 90;
+        if self.persist_rotation() {
+            if let Some(ref path) = self.rotation_path {
+                self.cache.lock().unwrap().image.set_rotation(path, degrees);
+            }
+        }
+        self.show_status(format!("Rotated to {}°", degrees));
+        self.render_validity.invalidate();
+    }
+
+    fn set_tagged(&mut self, tag: &str, tagged: bool) {
+        if let Some(path) = self.playback_manager.shown_file_path() {
+            let mut cache = self.cache.lock().unwrap();
+            cache.image.set_tagged(tag, path, tagged);
+            self.tags = cache.image.tags.clone();
+        }
+    }
+
+    /// Loads the first image after the current one that has the tag, starting
+    /// over from the first image of the folder.
+    fn jump_to_next_tagged(&mut self, tag: &str) {
+        let tagged = self.tags.get(tag).cloned();
+        let count = self.playback_manager.current_dir_len();
+        let current = self.playback_manager.current_file_index();
+        if let (Some(tagged), Some(count)) = (tagged, count) {
+            let start = current.map_or(0, |i| i + 1);
+            for offset in 0..count {
+                let index = (start + offset) % count;
+                let path = self.playback_manager.file_path_at_index(index);
+                let is_tagged = path.is_some_and(|path| {
+                    tagged.contains(path.to_string_lossy().as_ref())
+                });
+                if is_tagged {
+                    self.playback_manager
+                        .request_load(LoadRequest::LoadAtIndex(index));
+                    self.render_validity.invalidate();
+                    return;
+                }
+            }
+        }
+        self.show_status(format!(
+            "No images in this folder are tagged {}",
+            tag
+        ));
+    }
+
+    fn start_goto_file(&mut self) {
+        self.goto_file = Some(GotoFile {
+            query: String::new(),
+            best: None,
+        });
+        self.render_validity.invalidate();
+    }
+
+    /// Adds the character to the query of `goto_file`, or removes the last
+    /// one if it's `None`, and finds the best match again.
+    fn edit_goto_query(&mut self, ch: Option<char>) {
+        let mut goto = match self.goto_file.take() {
+            Some(goto) => goto,
+            None => return,
+        };
+        match ch {
+            Some(ch) => goto.query.push(ch),
+            None => {
+                goto.query.pop();
+            }
+        }
+        let case_sensitive = self
+            .configuration
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.fuzzy_case_sensitive)
+            .unwrap_or(false);
+        let count = self.playback_manager.current_dir_len().unwrap_or(0);
+        let mut best: Option<(i64, usize, String)> = None;
+        if !goto.query.is_empty() {
+            for index in 0..count {
+                let name = match self.playback_manager.file_path_at_index(index)
+                {
+                    Some(path) => match path.file_name() {
+                        Some(name) => name.to_string_lossy().into_owned(),
+                        None => continue,
+                    },
+                    None => continue,
+                };
+                let score = fuzzy_score(&goto.query, &name, case_sensitive);
+                if let Some(score) = score {
+                    if best.as_ref().is_none_or(|(s, _, _)| score > *s) {
+                        best = Some((score, index, name));
+                    }
+                }
+            }
+        }
+        goto.best = best.map(|(_, index, name)| (index, name));
+        self.goto_file = Some(goto);
+        self.render_validity.invalidate();
+    }
+
+    /// Closes `goto_file`, jumping to the best match if `accept` is true.
+    fn finish_goto_file(&mut self, accept: bool) {
+        let goto = match self.goto_file.take() {
+            Some(goto) => goto,
+            None => return,
+        };
+        if accept {
+            match goto.best {
+                Some((index, _)) => {
+                    self.playback_manager
+                        .request_load(LoadRequest::LoadAtIndex(index));
+                }
+                None if goto.query.is_empty() => (),
+                None => {
+                    self.show_status(format!("No file matches {}", goto.query))
+                }
+            }
+        }
+        self.render_validity.invalidate();
+    }
+
+    /// Starts another instance of the viewer on the current image. Its
+    /// navigation and zoom are independent of this window. It uses the
+    /// cache without writing to it, so that it can't overwrite the window
+    /// state or undo the changes of this window.
+    fn open_new_window(&mut self) {
+        let exe = match env::current_exe() {
+            Ok(exe) => exe,
+            Err(e) => {
+                self.show_status(format!("Could not open a new window: {}", e));
+                return;
+            }
+        };
+        let mut cmd = std::process::Command::new(exe);
+        cmd.arg("--cache-readonly");
+        if let Some(path) = self.playback_manager.shown_file_path() {
+            cmd.arg(path);
+        }
+        match cmd.spawn() {
+            Ok(mut child) => {
+                // Wait on a separate thread so that the process is reaped
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => {
+                self.show_status(format!("Could not open a new window: {}", e));
+            }
+        }
+    }
+
+    /// Asks the clipboard thread to save the image, which is opened by
+    /// `finish_paste` later.
+    fn paste_image(&mut self) {
+        let dir = self
+            .configuration
+            .borrow()
+            .directory
+            .clone()
+            .unwrap_or_default()
+            .paste_dir();
+        if let Some(clipboard_handler) = &mut self.clipboard_handler {
+            if clipboard_handler.request_paste(dir) {
+                self.paste_pending = true;
+            } else {
+                self.show_status("Nothing was pasted: the clipboard is busy");
+            }
+        }
+    }
+
+    fn finish_paste(&mut self, result: Result<PathBuf, String>) {
+        let path = match result {
+            Ok(path) => path,
+            Err(e) => {
+                self.show_status(format!("Nothing was pasted: {}", e));
+                return;
+            }
+        };
+        let target = self
+            .configuration
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.paste_target)
+            .unwrap_or_default();
+        let nothing_open = self.playback_manager.shown_file_path().is_none();
+        if target == PasteTarget::AppendPlaylist && !nothing_open {
+            self.playback_manager.append(path);
+            self.show_status("The pasted image was added to the list");
+        } else {
+            self.playback_manager.clear_appended();
+            self.open_path(path);
+        }
+    }
+
+    fn copy_metadata(&mut self) {
+        let path = match self.playback_manager.shown_file_path() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let text = {
+            let config = self.configuration.borrow();
+            let fields = config
+                .image
+                .as_ref()
+                .and_then(|image| image.metadata_fields.as_deref());
+            format_metadata(&path, fields)
+        };
+        if let Some(clipboard_handler) = &mut self.clipboard_handler {
+            if clipboard_handler.request_copy_text(text) {
+                self.copy_notifications.set_started();
+                self.clipboard_request_was_pending = true;
+            }
+        }
+    }
+
+    /// Opens a map of the place where the image was taken, found in its
+    /// EXIF data.
+    fn open_location(&mut self) {
+        let path = match self.playback_manager.shown_file_path() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let (latitude, longitude) = match gps_coordinates(&path) {
+            Some(coordinates) => coordinates,
+            None => {
+                self.show_status("The image has no location");
+                return;
+            }
+        };
+        let template = self
+            .configuration
+            .borrow()
+            .image
+            .as_ref()
+            .and_then(|image| image.maps_url_template.clone())
+            .unwrap_or_else(|| DEFAULT_MAPS_URL_TEMPLATE.to_owned());
+        let (lat, lon) =
+            (format!("{:.6}", latitude), format!("{:.6}", longitude));
+        let mut var_map = HashMap::with_capacity(2);
+        var_map.insert("${lat}", lat.as_str());
+        var_map.insert("${lon}", lon.as_str());
+        let url = substitute_command_parameters(&template, &var_map);
+        if let Err(e) = open::that(&url) {
+            self.show_status(format!("Could not open the map: {}", e));
+        }
+    }
+
+    fn load_full_resolution(&mut self) {
+        if self.playback_manager.current_downscaled() {
+            self.playback_manager.load_full_resolution();
+            self.show_status("Loading the full resolution");
+            self.render_validity.invalidate();
+        } else {
+            self.show_status("Already at full resolution");
+        }
     }
 
     pub fn set_img_size_to_orig(&mut self) {
+        self.store_fit_mode(FitMode::Original);
         self.img_texel_size = 1.0;
         self.scaling = ScalingMode::Fixed;
         self.update_scaling_buttons();
@@ -386,7 +1412,14 @@ impl PictureWidgetData {
     }
 
     pub fn set_img_size_to_fit(&mut self, stretch: bool) {
-        {
+        let mode = if stretch {
+            FitMode::Fit
+        } else {
+            FitMode::FitBest
+        };
+        // The global mode stays for the other files when the shown one
+        // remembers its own
+        if !self.store_fit_mode(mode) {
             let mut cache = self.cache.lock().unwrap();
             cache.image.fit_stretches = stretch;
         }
@@ -399,8 +1432,9 @@ impl PictureWidgetData {
         self.render_validity.invalidate();
     }
 
-    pub fn toggle_antialias(&mut self) {
-        let aa = match self.antialiasing {
+    /// Returns the opposite of what `aa` currently looks like.
+    fn toggled_antialias(&self, aa: Antialias) -> Antialias {
+        match aa {
             Antialias::Auto
                 if self.img_texel_size < AA_TEXEL_SIZE_THRESHOLD =>
             {
@@ -408,12 +1442,57 @@ impl PictureWidgetData {
             }
             Antialias::Auto | Antialias::Never => Antialias::Always,
             Antialias::Always => Antialias::Never,
-        };
+        }
+    }
+
+    pub fn toggle_antialias(&mut self) {
+        let aa = self.toggled_antialias(self.antialiasing);
         self.antialiasing = aa;
         self.cache.lock().unwrap().image.antialiasing = aa;
         self.render_validity.invalidate();
     }
 
+    /// The antialiasing of the shown image, which is its override if it has
+    /// one.
+    fn effective_antialiasing(&self) -> Antialias {
+        self.playback_manager
+            .shown_file_path()
+            .as_ref()
+            .and_then(|path| {
+                self.cache.lock().unwrap().image.antialias_override(path)
+            })
+            .unwrap_or(self.antialiasing)
+    }
+
+    pub fn toggle_antialias_this_image(&mut self) {
+        if let Some(path) = self.playback_manager.shown_file_path().clone() {
+            let aa = self.toggled_antialias(self.effective_antialiasing());
+            self.cache
+                .lock()
+                .unwrap()
+                .image
+                .set_antialias_override(&path, Some(aa));
+            self.render_validity.invalidate();
+        }
+    }
+
+    pub fn clear_antialias_override(&mut self) {
+        if let Some(path) = self.playback_manager.shown_file_path() {
+            self.cache
+                .lock()
+                .unwrap()
+                .image
+                .set_antialias_override(path, None);
+            self.render_validity.invalidate();
+        }
+    }
+
+    pub fn toggle_grayscale(&mut self) {
+        self.grayscale = !self.grayscale;
+        self.cache.lock().unwrap().image.grayscale = self.grayscale;
+        self.render_validity.invalidate();
+    }
+
     pub fn set_automatic_antialias(&mut self) {
         self.antialiasing = Antialias::Auto;
         self.cache.lock().unwrap().image.antialiasing = Antialias::Auto;
@@ -464,12 +1543,16 @@ pub struct PictureWidget {
 impl PictureWidget {
     add_common_widget_functions!(data);
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         display: &Display,
         window: &Rc<Window>,
         bottom_bar: Rc<BottomBar>,
         left_to_pan_hint: Rc<HelpScreen>,
         copy_notifications: CopyNotifications,
+        progress_overlay: ProgressOverlay,
+        sidecar_overlay: SidecarOverlay,
+        histogram: Histogram,
         configuration: Rc<RefCell<Configuration>>,
         cache: Arc<Mutex<Cache>>,
     ) -> PictureWidget {
@@ -486,8 +1569,10 @@ impl PictureWidget {
         .unwrap();
 
         let scaling;
+        let grayscale;
         {
             let cache = cache.lock().unwrap();
+            grayscale = cache.image.grayscale;
             if cache.image.fit_stretches {
                 scaling = ScalingMode::FitStretch;
             } else {
@@ -507,30 +1592,91 @@ impl PictureWidget {
             "always" => Antialias::Always,
             "never" => Antialias::Never,
             "previous" => cache.lock().unwrap().image.antialiasing,
-            val => {
-                eprintln!(
-                    "Illegal configuration value {:?} for antialiasing!",
-                    val
-                );
-                eprintln!(
-                    r#"Allowed values are "auto", "always", "never" and "previous"."#
-                );
-                Antialias::default()
-            }
+            // Reported by `Configuration::validate`
+            _ => Antialias::default(),
         };
 
+        let decode_settings = DecodeSettings {
+            filter: configuration.borrow().filter.clone().map(ImageFilter::new),
+            auto_orient: configuration
+                .borrow()
+                .image
+                .as_ref()
+                .and_then(|s| s.auto_orient)
+                .unwrap_or(true),
+            color_management: configuration
+                .borrow()
+                .image
+                .as_ref()
+                .and_then(|s| s.color_management)
+                .unwrap_or(false),
+            max_dimension: configuration
+                .borrow()
+                .image
+                .as_ref()
+                .and_then(|s| s.max_dimension),
+            jpeg_decode_scale: configuration
+                .borrow()
+                .image
+                .as_ref()
+                .and_then(|s| s.jpeg_decode_scale)
+                .filter(|scale| [1, 2, 4, 8].contains(scale))
+                .unwrap_or(1),
+            thumbnailer: configuration
+                .borrow()
+                .image
+                .as_ref()
+                .and_then(Thumbnailer::from_config),
+        };
+        let sidecars =
+            configuration.borrow().sidecar.clone().map(Sidecars::new);
+        let reduce_motion = configuration
+            .borrow()
+            .window
+            .as_ref()
+            .and_then(|w| w.reduce_motion)
+            .unwrap_or_else(os_prefers_reduced_motion);
+        let enumeration = configuration
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.enumeration)
+            .unwrap_or_default();
+        let show_hidden = configuration
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.show_hidden)
+            .unwrap_or(false);
+        let follow_symlinks = configuration
+            .borrow()
+            .directory
+            .as_ref()
+            .and_then(|d| d.follow_symlinks)
+            .unwrap_or(false);
+        let tags = cache.lock().unwrap().image.tags.clone();
+
         let mut data = PictureWidgetData {
             placement: Default::default(),
             drawn_bounds: Default::default(),
             visible: true,
             prev_draw_size: Default::default(),
+            resize_settles_at: None,
+            quality_path: None,
+            quality_settles_at: Instant::now(),
             click: false,
             hover: false,
             configuration,
             cache,
-            playback_manager: PlaybackManager::new(),
+            playback_manager: PlaybackManager::new(
+                decode_settings,
+                enumeration,
+                show_hidden,
+                follow_symlinks,
+            ),
             clipboard_handler: Some(ClipboardHandler::new()),
             clipboard_request_was_pending: false,
+            paste_pending: false,
             render_validity: Default::default(),
 
             program,
@@ -539,6 +1685,7 @@ impl PictureWidget {
             scaling,
             img_pos: Default::default(),
             antialiasing,
+            grayscale,
             hor_pan_input: MovementDir::None,
             ver_pan_input: MovementDir::None,
             zoom_input: MovementDir::None,
@@ -549,12 +1696,33 @@ impl PictureWidget {
             last_mouse_pos: Default::default(),
             panning: false,
             hover_state: HoverState::None,
+            last_drop_time: Instant::now() - Duration::from_secs(10),
+            scroll_path: None,
+            rotation: 0,
+            rotation_path: None,
+            fit_path: None,
+            fit_overridden: false,
             last_cam_move_time: Instant::now(),
             first_draw: true,
             next_update: NextUpdate::Latest,
             bottom_bar,
             left_to_pan_hint,
             copy_notifications,
+            progress_overlay,
+            sidecar_overlay,
+            histogram,
+            status_message: None,
+            goto_file: None,
+            paused_slideshow: None,
+            reduce_motion,
+            sidecars,
+            sidecar_path: None,
+            file_size_path: None,
+            file_size: None,
+            sidecar_text: None,
+            tags,
+            event_hooks: EventHooks::default(),
+            on_cycle_palette: None,
             window: Rc::downgrade(window),
         };
         data.update_scaling_buttons();
@@ -563,6 +1731,13 @@ impl PictureWidget {
         }
     }
 
+    pub fn set_on_cycle_palette<F>(&self, callback: F)
+    where
+        F: Fn() -> Option<String> + 'static,
+    {
+        self.data.borrow_mut().on_cycle_palette = Some(Rc::new(callback));
+    }
+
     pub fn set_bright_shade(&self, shade: f32) {
         let mut borrowed = self.data.borrow_mut();
         borrowed.bright_shade = shade;
@@ -589,36 +1764,77 @@ impl PictureWidget {
 
     pub fn jump_to_path<P: Into<PathBuf>>(&self, path: P) {
         let mut borrowed = self.data.borrow_mut();
-        borrowed
-            .playback_manager
-            .request_load(LoadRequest::FilePath(path.into()));
+        borrowed.open_path(path.into());
         borrowed.render_validity.invalidate();
     }
 
     fn handle_key_input(&self, input_key: &str, modifiers: ModifiersState) {
+        self.run_actions(ActionTrigger::Key {
+            input_key,
+            modifiers,
+        });
+    }
+
+    /// Runs the action as if its key was pressed. Custom commands are only
+    /// run by keys.
+    pub fn trigger_action(&self, action_name: &str) {
+        self.run_actions(ActionTrigger::Action(action_name));
+    }
+
+    fn run_actions(&self, trigger: ActionTrigger) {
+        // The palette is changed before borrowing the data, because
+        // changing it sets the colors of this widget as well.
+        let cycle_palette = {
+            let data = self.data.borrow();
+            match data.on_cycle_palette {
+                Some(ref callback)
+                    if trigger
+                        .triggers(&data.configuration, CYCLE_PALETTE_NAME) =>
+                {
+                    Some(callback.clone())
+                }
+                _ => None,
+            }
+        };
+        let palette_name = cycle_palette.map(|callback| callback());
         let mut borrowed = self.data.borrow_mut();
+        if let Some(palette_name) = palette_name {
+            borrowed.show_status(match palette_name {
+                Some(name) => format!("Palette: {}", name),
+                None => "No palettes are configured".into(),
+            });
+        }
         macro_rules! triggered {
             ($action_name:ident) => {
-                action_triggered(
-                    &borrowed.configuration,
-                    $action_name,
-                    input_key,
-                    modifiers,
-                )
+                trigger.triggers(&borrowed.configuration, $action_name)
             };
         }
+        let from_key = matches!(trigger, ActionTrigger::Key { .. });
+        if triggered!(SLIDESHOW_RESUME_NAME) {
+            borrowed.resume_slideshow();
+        } else if from_key
+            && !triggered!(PLAY_PRESENT_NAME)
+            && !triggered!(PLAY_PRESENT_RND_NAME)
+        {
+            // The keys that control the presentation are handled below.
+            borrowed.pause_slideshow_on_input();
+        }
         if triggered!(TOGGLE_FULLSCREEN_NAME) {
             if let Some(window) = borrowed.window.upgrade() {
                 let fullscreen = !window.fullscreen();
                 window.set_fullscreen(fullscreen);
-                borrowed.bottom_bar.set_visible_if_should_show(!fullscreen);
+                borrowed.bottom_bar.set_fullscreen(fullscreen);
             }
         }
+        if triggered!(TOGGLE_BOTTOM_BAR_NAME) {
+            let mut cache = borrowed.cache.lock().unwrap();
+            borrowed.bottom_bar.toggle(&mut cache.window);
+        }
         if triggered!(ESCAPE_NAME) {
             if let Some(window) = borrowed.window.upgrade() {
                 if window.fullscreen() {
                     window.set_fullscreen(false);
-                    borrowed.bottom_bar.set_visible_if_should_show(true);
+                    borrowed.bottom_bar.set_fullscreen(false);
                 } else {
                     request_exit();
                 }
@@ -638,6 +1854,9 @@ impl PictureWidget {
                 .request_load(LoadRequest::LoadPrevious);
             borrowed.render_validity.invalidate();
         }
+        if triggered!(LOAD_FULL_RESOLUTION_NAME) {
+            borrowed.load_full_resolution();
+        }
         if triggered!(IMG_NEXT_NAME) {
             borrowed
                 .playback_manager
@@ -659,6 +1878,31 @@ impl PictureWidget {
         if triggered!(SET_AUTOMATIC_ANTIALIAS_NAME) {
             borrowed.set_automatic_antialias();
         }
+        if triggered!(TOGGLE_ANTIALIAS_THIS_IMAGE_NAME) {
+            borrowed.toggle_antialias_this_image();
+        }
+        if triggered!(CLEAR_ANTIALIAS_OVERRIDE_NAME) {
+            borrowed.clear_antialias_override();
+        }
+        if triggered!(RESET_FIT_OVERRIDE_NAME) {
+            borrowed.reset_fit_override();
+        }
+        if triggered!(TOGGLE_GRAYSCALE_NAME) {
+            borrowed.toggle_grayscale();
+        }
+        if triggered!(TOGGLE_HISTOGRAM_NAME) {
+            borrowed.histogram.toggle();
+            borrowed.render_validity.invalidate();
+        }
+        if triggered!(ROTATE_CW_NAME) {
+            borrowed.rotate(1);
+        }
+        if triggered!(ROTATE_CCW_NAME) {
+            borrowed.rotate(3);
+        }
+        if triggered!(SET_FOLDER_ROTATION_NAME) {
+            borrowed.set_folder_rotation();
+        }
         if triggered!(PLAY_PRESENT_NAME) {
             match borrowed.playback_manager.playback_state() {
                 PlaybackState::Present => {
@@ -709,16 +1953,61 @@ impl PictureWidget {
                 }
             }
         }
+        if triggered!(COPY_METADATA_NAME) {
+            borrowed.copy_metadata();
+        }
+        if triggered!(OPEN_LOCATION_NAME) {
+            borrowed.open_location();
+        }
+        if triggered!(PASTE_IMAGE_NAME) {
+            borrowed.paste_image();
+        }
+        if triggered!(NEW_WINDOW_NAME) {
+            borrowed.open_new_window();
+        }
+        if triggered!(GOTO_FILE_NAME) {
+            borrowed.start_goto_file();
+        }
+        #[cfg(feature = "wallpaper")]
+        if triggered!(SET_WALLPAPER_NAME) {
+            borrowed.set_wallpaper();
+        }
+        if triggered!(BROWSE_FOLDER_NAME) {
+            if let Err(e) = borrowed.playback_manager.browse_folder() {
+                eprintln!("Error while browsing the folder {:?}", e);
+            }
+            borrowed.render_validity.invalidate();
+        }
+        for tag in trigger.parameters(&borrowed.configuration, TAG_PREFIX) {
+            borrowed.set_tagged(&tag, true);
+        }
+        for tag in trigger.parameters(&borrowed.configuration, UNTAG_PREFIX) {
+            borrowed.set_tagged(&tag, false);
+        }
+        for tag in
+            trigger.parameters(&borrowed.configuration, NEXT_TAGGED_PREFIX)
+        {
+            borrowed.jump_to_next_tagged(&tag);
+        }
+        let (input_key, modifiers) = match trigger {
+            ActionTrigger::Key {
+                input_key,
+                modifiers,
+            } => (input_key, modifiers),
+            ActionTrigger::Action(_) => return,
+        };
+        let mut advance = false;
         if let Some(img_path) = borrowed.playback_manager.shown_file_path() {
             if let Some(folder_path) = img_path.parent() {
                 let img_and_folder = (img_path.to_str(), folder_path.to_str());
                 if let (Some(img_path), Some(folder_path)) = img_and_folder {
-                    execute_triggered_commands(
+                    advance = execute_triggered_commands(
                         borrowed.configuration.clone(),
                         input_key,
                         modifiers,
                         img_path,
                         folder_path,
+                        &borrowed.tags,
                     );
                 } else {
                     eprintln!("Could not convert the image path to utf8. Path: '{:?}'", img_path);
@@ -730,6 +2019,12 @@ impl PictureWidget {
                 );
             }
         }
+        if advance {
+            borrowed
+                .playback_manager
+                .request_load(LoadRequest::LoadNext);
+            borrowed.render_validity.invalidate();
+        }
     }
 }
 
@@ -753,20 +2048,42 @@ impl Widget for PictureWidget {
         let new_texture = data.playback_manager.image_texture();
         let curr_file_index = data.playback_manager.current_file_index();
         let curr_dir_len = data.playback_manager.current_dir_len();
-        if let (Some(curr_file_index), Some(curr_dir_len)) =
-            (curr_file_index, curr_dir_len)
-        {
+        let position = curr_file_index.zip(curr_dir_len);
+        if let Some((curr_file_index, curr_dir_len)) = position {
             data.bottom_bar
                 .slider
                 .set_steps(curr_dir_len as u32, curr_file_index as u32);
         }
         //data.slider.set_step_bg(data.playback_manager.cached_from_dir());
+        data.update_sidecar();
+        data.update_file_size();
+        let configuration = data.configuration.clone();
+        let shown_file_path = data.playback_manager.shown_file_path().clone();
+        let PictureWidgetData {
+            event_hooks, tags, ..
+        } = &mut *data;
+        let next_event =
+            event_hooks.update(&configuration.borrow(), &shown_file_path, tags);
+        if let Some(next_event) = next_event {
+            data.next_update = data
+                .next_update
+                .aggregate(NextUpdate::WaitUntil(next_event));
+        }
+        if let Some(until) = data.status_message.as_ref().map(|m| m.until) {
+            if until <= now {
+                data.status_message = None;
+            } else {
+                data.next_update =
+                    data.next_update.aggregate(NextUpdate::WaitUntil(until));
+            }
+        }
         let playback_state = data.playback_manager.playback_state();
         data.set_window_title_filename(
             window,
             playback_state,
             data.playback_manager.shown_file_path(),
         );
+        data.update_bottom_bar_info(position);
         if prev_texture.is_none() != new_texture.is_none() {
             data.render_validity.invalidate();
         } else if let (Some(prev_tex), Some(new_tex)) =
@@ -776,7 +2093,24 @@ impl Widget for PictureWidget {
                 data.render_validity.invalidate();
             }
         }
-        if let Some(clipboard_handler) = &data.clipboard_handler {
+        if data.paste_pending {
+            let pasted = data
+                .clipboard_handler
+                .as_ref()
+                .and_then(|handler| handler.take_pasted());
+            match pasted {
+                Some(result) => {
+                    data.paste_pending = false;
+                    data.finish_paste(result);
+                }
+                None => {
+                    let next_update = now + Duration::from_millis(100);
+                    data.next_update = data
+                        .next_update
+                        .aggregate(NextUpdate::WaitUntil(next_update));
+                }
+            }
+        } else if let Some(clipboard_handler) = &data.clipboard_handler {
             let clipboard_result = clipboard_handler.try_get_result();
             let request_pending = clipboard_result.is_none();
             if data.clipboard_request_was_pending != request_pending {
@@ -801,8 +2135,32 @@ impl Widget for PictureWidget {
             data.render_validity.invalidate();
             data.next_update = NextUpdate::Soonest;
         }
+        if let Some(at) = data.resize_settles_at {
+            if at <= now {
+                data.render_validity.invalidate();
+                data.next_update = NextUpdate::Soonest;
+            } else {
+                data.next_update =
+                    data.next_update.aggregate(NextUpdate::WaitUntil(at));
+            }
+        }
+        data.update_paused_slideshow();
+        data.update_reduced_quality(now);
         let next_copy_noti_update = data.copy_notifications.update();
         data.next_update = data.next_update.aggregate(next_copy_noti_update);
+        let next_overlay_update = data
+            .progress_overlay
+            .update(&configuration.borrow(), position);
+        data.next_update = data.next_update.aggregate(next_overlay_update);
+        let histogram_mode = configuration
+            .borrow()
+            .image
+            .as_ref()
+            .and_then(|s| s.histogram_mode)
+            .unwrap_or_default();
+        let levels = data.playback_manager.current_levels();
+        let grayscale = data.grayscale;
+        data.histogram.update(levels, grayscale, histogram_mode);
         data.next_update
     }
 
@@ -817,8 +2175,11 @@ impl Widget for PictureWidget {
             if !data.visible {
                 return Ok(data.next_update);
             }
+            data.update_rotation();
+            data.update_fit_override();
             data.update_image_transform(context.dpi_scale_factor);
             data.apply_camera_movement(context.dpi_scale_factor);
+            data.update_remembered_scroll(context.dpi_scale_factor);
             texture = data.get_texture();
         }
         if let Some(texture) = texture {
@@ -843,6 +2204,8 @@ impl Widget for PictureWidget {
         match event.kind {
             EventKind::MouseMove => {
                 let mut borrowed = self.data.borrow_mut();
+                borrowed.pause_slideshow_on_input();
+                borrowed.progress_overlay.wake();
                 borrowed.hover =
                     borrowed.drawn_bounds.contains(event.cursor_pos);
                 if borrowed.panning {
@@ -879,9 +2242,7 @@ impl Widget for PictureWidget {
                                         window.set_fullscreen(fullscreen);
                                         borrowed
                                             .bottom_bar
-                                            .set_visible_if_should_show(
-                                                !fullscreen,
-                                            );
+                                            .set_fullscreen(fullscreen);
                                     }
                                     None => unreachable!(),
                                 }
@@ -902,6 +2263,33 @@ impl Widget for PictureWidget {
                 let delta = delta.vec.y * 0.375;
                 borrowed.zoom_image(event.cursor_pos, delta);
             }
+            EventKind::ReceivedCharacter(ch)
+                if self.data.borrow().goto_file.is_some() =>
+            {
+                if !ch.is_control() {
+                    self.data.borrow_mut().edit_goto_query(Some(ch));
+                }
+            }
+            EventKind::KeyInput { input }
+                if self.data.borrow().goto_file.is_some() =>
+            {
+                if input.state == ElementState::Pressed {
+                    let mut borrowed = self.data.borrow_mut();
+                    match input.virtual_keycode {
+                        Some(VirtualKeyCode::Escape) => {
+                            borrowed.finish_goto_file(false)
+                        }
+                        Some(VirtualKeyCode::Return)
+                        | Some(VirtualKeyCode::NumpadEnter) => {
+                            borrowed.finish_goto_file(true)
+                        }
+                        Some(VirtualKeyCode::Back) => {
+                            borrowed.edit_goto_query(None)
+                        }
+                        _ => (),
+                    }
+                }
+            }
             EventKind::ReceivedCharacter(ch) => {
                 //println!("Got char {}", ch);
                 // When the control key is held down, this character is going to be the keycode
@@ -941,20 +2329,26 @@ impl Widget for PictureWidget {
                     let pressed = input.state == ElementState::Pressed;
 
                     macro_rules! movement_trigger {
-                        ($input:expr, $vel:expr, $name:expr, $dir:expr) => {
+                        ($input:expr, $vel:expr, $name:expr, $dir:expr, $step:expr) => {
                             if action_triggered(
                                 &borrowed.configuration,
                                 $name,
                                 input_key_str.as_str(),
                                 event.modifiers,
                             ) {
-                                if $input == $dir && !pressed {
-                                    $input = MovementDir::None;
-                                    $vel = 0.0;
-                                }
-                                if $input != $dir && pressed {
-                                    borrowed.camera_movement_will_start();
-                                    $input = $dir;
+                                if borrowed.reduce_motion {
+                                    if pressed {
+                                        borrowed.step_camera($step);
+                                    }
+                                } else {
+                                    if $input == $dir && !pressed {
+                                        $input = MovementDir::None;
+                                        $vel = 0.0;
+                                    }
+                                    if $input != $dir && pressed {
+                                        borrowed.camera_movement_will_start();
+                                        $input = $dir;
+                                    }
                                 }
                             }
                         };
@@ -964,52 +2358,60 @@ impl Widget for PictureWidget {
                         borrowed.zoom_input,
                         borrowed.zoom_vel,
                         ZOOM_IN_NAME,
-                        MovementDir::Positive
+                        MovementDir::Positive,
+                        CameraStep::Zoom(1.0)
                     );
                     movement_trigger!(
                         borrowed.zoom_input,
                         borrowed.zoom_vel,
                         ZOOM_OUT_NAME,
-                        MovementDir::Negative
+                        MovementDir::Negative,
+                        CameraStep::Zoom(-1.0)
                     );
 
                     movement_trigger!(
                         borrowed.hor_pan_input,
                         borrowed.hor_pan_vel,
                         PAN_LEFT_NAME,
-                        MovementDir::Positive
+                        MovementDir::Positive,
+                        CameraStep::Pan(1.0, 0.0)
                     );
                     movement_trigger!(
                         borrowed.hor_pan_input,
                         borrowed.hor_pan_vel,
                         PAN_RIGHT_NAME,
-                        MovementDir::Negative
+                        MovementDir::Negative,
+                        CameraStep::Pan(-1.0, 0.0)
                     );
 
                     movement_trigger!(
                         borrowed.ver_pan_input,
                         borrowed.ver_pan_vel,
                         PAN_UP_NAME,
-                        MovementDir::Positive
+                        MovementDir::Positive,
+                        CameraStep::Pan(0.0, 1.0)
                     );
                     movement_trigger!(
                         borrowed.ver_pan_input,
                         borrowed.ver_pan_vel,
                         PAN_DOWN_NAME,
-                        MovementDir::Negative
+                        MovementDir::Negative,
+                        CameraStep::Pan(0.0, -1.0)
                     );
                 }
             }
             EventKind::DroppedFile(ref path) => {
                 let mut borrowed = self.data.borrow_mut();
-                borrowed
-                    .playback_manager
-                    .request_load(LoadRequest::FilePath(path.clone()));
+                borrowed.handle_dropped_file(path);
                 borrowed.hover_state = HoverState::None;
                 borrowed.render_validity.invalidate();
             }
             EventKind::HoveredFile(ref path) => {
                 let mut borrowed = self.data.borrow_mut();
+                if borrowed.drop_behavior() != DropBehavior::ReplacePlaylist {
+                    // Only show a preview if dropping would open the item.
+                    return;
+                }
                 match borrowed.hover_state {
                     HoverState::None => {
                         let curr_path = borrowed
@@ -1054,9 +2456,12 @@ impl Widget for PictureWidget {
                 }
             }
             EventKind::CloseRequested => {
-                let mut borrowed = self.data.borrow_mut();
-                // Just let it drop.
-                borrowed.clipboard_handler.take();
+                // The close request may have been turned into `on_close`
+                if exit_requested() {
+                    let mut borrowed = self.data.borrow_mut();
+                    // Just let it drop.
+                    borrowed.clipboard_handler.take();
+                }
             }
         }
     }
@@ -1085,6 +2490,16 @@ impl Drop for PictureWidget {
     }
 }
 
+fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
 fn draw_tex_grid(
     data: Ref<PictureWidgetData>,
     target: &mut Frame,
@@ -1101,9 +2516,12 @@ fn draw_tex_grid(
         ..Default::default()
     };
 
-    let img_phys_w = texture.w as f32;
-    let img_phys_h = texture.h as f32;
+    let img_phys_w = texture.original_w as f32;
+    let img_phys_h = texture.original_h as f32;
     let img_height_over_width = img_phys_h / img_phys_w;
+    // The number of screen pixels covered by a pixel of the texture, which is
+    // larger than `img_texel_size` if the image was decoded at a smaller size
+    let tex_texel_size = data.img_texel_size * img_phys_w / texture.w as f32;
     let image_display_width =
         data.img_texel_size * img_phys_w / context.dpi_scale_factor;
     let image_display_height = image_display_width * img_height_over_width;
@@ -1150,6 +2568,7 @@ fn draw_tex_grid(
 
     // let img_logical_w = img_w / context.dpi_scale_factor;
     // let img_logical_h = img_h / context.dpi_scale_factor;
+    let antialiasing = data.effective_antialiasing();
     let cell_phy_step = texture.cell_step_size;
     for cell_tex in texture.tex_grid.iter() {
         let (cell_phys_w, cell_phys_h) = cell_tex.tex.dimensions();
@@ -1162,13 +2581,13 @@ fn draw_tex_grid(
         // The grid is constructed so that it is exactly of size (1, 1) and is located at (0, 0)
         // This allows to leave most of the image transformation logic unchanged.
         let cell_scaling = Matrix4::from_nonuniform_scale(
-            cell_phys_w as f32 / img_phys_w,
-            cell_phys_h as f32 / img_phys_h,
+            cell_phys_w as f32 / texture.w as f32,
+            cell_phys_h as f32 / texture.h as f32,
             1.0,
         );
         let cell_translation = Matrix4::from_translation(Vector3::new(
-            cell_phy_offset_x as f32 / img_phys_w,
-            cell_phy_offset_y as f32 / img_phys_h,
+            cell_phy_offset_x as f32 / texture.w as f32,
+            cell_phy_offset_y as f32 / texture.h as f32,
             0.0,
         ));
 
@@ -1186,7 +2605,7 @@ fn draw_tex_grid(
 			.minify_filter(gelatin::glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
 			.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp);
 
-        let filter = match data.antialiasing {
+        let filter = match antialiasing {
             Antialias::Auto
                 if data.img_texel_size < AA_TEXEL_SIZE_THRESHOLD =>
             {
@@ -1199,10 +2618,11 @@ fn draw_tex_grid(
 
         // building the uniforms
         let lod_level =
-            ((1.0 / data.img_texel_size).log2().max(0.0) + 0.125).floor();
+            ((1.0 / tex_texel_size).log2().max(0.0) + 0.125).floor();
         let uniforms = uniform! {
             matrix: Into::<[[f32; 4]; 4]>::into(transform),
             bright_shade: data.bright_shade,
+            grayscale: data.grayscale,
             tex: sampler,
             lod_level: lod_level,
         };
@@ -0,0 +1,158 @@
+use std::{
+    rc::{Rc, Weak},
+    time::{Duration, Instant},
+};
+
+use gelatin::{
+    image::{Rgba, RgbaImage},
+    label::Label,
+    misc::*,
+    picture::Picture,
+    NextUpdate, Widget,
+};
+
+use crate::configuration::{Configuration, Corner};
+
+/// The overlay hides after the mouse hasn't moved and no other image was
+/// shown for this long.
+const HIDE_DELAY: Duration = Duration::from_secs(2);
+
+/// The size of a pixel of the glyphs in image pixels.
+const GLYPH_SCALE: u32 = 4;
+/// The image is drawn at half of its size so that it's sharp on high DPI
+/// screens.
+const IMAGE_PIXELS_PER_POINT: f32 = 2.0;
+const GLYPH_W: u32 = 3;
+const GLYPH_H: u32 = 5;
+const PADDING: u32 = 3;
+
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 160]);
+const FOREGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// The rows of a 3 by 5 glyph, the lowest three bits of each row from left
+/// to right.
+fn glyph(ch: char) -> [u8; 5] {
+    match ch {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        _ => [0; 5],
+    }
+}
+
+/// Draws the text with the built-in glyphs, which only cover digits and the
+/// slash, on a translucent background.
+fn render_text(text: &str) -> RgbaImage {
+    let count = text.chars().count() as u32;
+    let width = (count * (GLYPH_W + 1) - 1 + 2 * PADDING) * GLYPH_SCALE;
+    let height = (GLYPH_H + 2 * PADDING) * GLYPH_SCALE;
+    let mut image = RgbaImage::from_pixel(width, height, BACKGROUND);
+    for (i, ch) in text.chars().enumerate() {
+        let left = PADDING + i as u32 * (GLYPH_W + 1);
+        for (row, bits) in glyph(ch).iter().enumerate() {
+            for col in 0..GLYPH_W {
+                if bits & (1 << (GLYPH_W - 1 - col)) == 0 {
+                    continue;
+                }
+                let x = (left + col) * GLYPH_SCALE;
+                let y = (PADDING + row as u32) * GLYPH_SCALE;
+                for dy in 0..GLYPH_SCALE {
+                    for dx in 0..GLYPH_SCALE {
+                        image.put_pixel(x + dx, y + dy, FOREGROUND);
+                    }
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Shows the position of the current image in the folder, like "12 / 340",
+/// in a corner of the picture.
+pub struct ProgressOverlay {
+    pub widget: Weak<Label>,
+    position: Option<(usize, usize)>,
+    shown_time: Instant,
+}
+
+impl ProgressOverlay {
+    pub fn new(widget: &Rc<Label>) -> ProgressOverlay {
+        widget.set_icon(None);
+        widget.set_ignore_layout(true);
+        widget.set_margin_all(4.0);
+        widget.set_visible(false);
+
+        ProgressOverlay {
+            widget: Rc::downgrade(widget),
+            position: None,
+            shown_time: Instant::now(),
+        }
+    }
+
+    /// Shows the overlay again, called when the mouse moves.
+    pub fn wake(&mut self) {
+        self.shown_time = Instant::now();
+    }
+
+    /// `position` is the index of the current image and the number of images.
+    pub fn update(
+        &mut self,
+        config: &Configuration,
+        position: Option<(usize, usize)>,
+    ) -> NextUpdate {
+        let widget = self.widget.upgrade().unwrap();
+        let window = config.window.as_ref();
+        let enabled = window.and_then(|w| w.progress_overlay).unwrap_or(false);
+        if !enabled || position.is_none() {
+            set_visible(&widget, false);
+            return NextUpdate::Latest;
+        }
+        if position != self.position {
+            self.position = position;
+            self.shown_time = Instant::now();
+            let (index, count) = position.unwrap();
+            let image = render_text(&format!("{} / {}", index + 1, count));
+            let width = image.width() as f32 / IMAGE_PIXELS_PER_POINT;
+            let height = image.height() as f32 / IMAGE_PIXELS_PER_POINT;
+            widget.set_width(Length::Fixed(width));
+            widget.set_height(Length::Fixed(height));
+            widget.set_icon(Some(Rc::new(Picture::from_image(image))));
+
+            let corner = window
+                .and_then(|w| w.progress_overlay_position)
+                .unwrap_or_default();
+            let (horizontal, vertical) = match corner {
+                Corner::TopLeft => (Alignment::Start, Alignment::Start),
+                Corner::TopRight => (Alignment::End, Alignment::Start),
+                Corner::BottomLeft => (Alignment::Start, Alignment::End),
+                Corner::BottomRight => (Alignment::End, Alignment::End),
+            };
+            widget.set_horizontal_align(horizontal);
+            widget.set_vertical_align(vertical);
+        }
+
+        let hide_time = self.shown_time + HIDE_DELAY;
+        if Instant::now() < hide_time {
+            set_visible(&widget, true);
+            NextUpdate::WaitUntil(hide_time)
+        } else {
+            set_visible(&widget, false);
+            NextUpdate::Latest
+        }
+    }
+}
+
+/// Only changes the visibility if it's different, as that redraws the window.
+fn set_visible(widget: &Label, visible: bool) {
+    if widget.visible() != visible {
+        widget.set_visible(visible);
+    }
+}
@@ -0,0 +1,58 @@
+use std::rc::{Rc, Weak};
+
+use gelatin::{label::Label, misc::*, picture::Picture};
+
+use super::text::{render_text, TextStyle, IMAGE_PIXELS_PER_POINT};
+
+const STYLE: TextStyle = TextStyle {
+    color: [255, 255, 255],
+    background: [0, 0, 0, 160],
+    font_size: 14.0,
+    height: 28.0,
+    padding: 8.0,
+};
+
+/// Shows the text of the sidecar of the current image over the top of the
+/// picture, for as long as the image is shown.
+pub struct SidecarOverlay {
+    pub widget: Weak<Label>,
+    text: Option<String>,
+}
+
+impl SidecarOverlay {
+    pub fn new(widget: &Rc<Label>) -> SidecarOverlay {
+        widget.set_icon(None);
+        widget.set_ignore_layout(true);
+        widget.set_margin_all(4.0);
+        widget.set_horizontal_align(Alignment::Center);
+        widget.set_vertical_align(Alignment::Start);
+        widget.set_visible(false);
+
+        SidecarOverlay {
+            widget: Rc::downgrade(widget),
+            text: None,
+        }
+    }
+
+    /// Shows the text, or hides the overlay if there's none.
+    pub fn set_text(&mut self, text: Option<&str>) {
+        if self.text.as_deref() == text {
+            return;
+        }
+        self.text = text.map(str::to_owned);
+        let widget = self.widget.upgrade().unwrap();
+        match text.and_then(|text| render_text(text, &STYLE)) {
+            Some(image) => {
+                let width = image.width() as f32 / IMAGE_PIXELS_PER_POINT;
+                widget.set_width(Length::Fixed(width));
+                widget.set_height(Length::Fixed(STYLE.height));
+                widget.set_icon(Some(Rc::new(Picture::from_image(image))));
+                widget.set_visible(true);
+            }
+            None => {
+                widget.set_icon(None);
+                widget.set_visible(false);
+            }
+        }
+    }
+}
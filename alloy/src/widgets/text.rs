@@ -0,0 +1,115 @@
+//! Renders text into images with the system fonts, because the widgets can
+//! only draw pictures.
+
+use std::cell::RefCell;
+
+use gelatin::image::RgbaImage;
+use usvg::NodeExt;
+
+/// The images have this many pixels per point so that the text is sharp on
+/// high DPI screens.
+pub const IMAGE_PIXELS_PER_POINT: f32 = 2.0;
+
+/// Tried in order, and the first font that was found is the last resort.
+/// They aren't quoted because `usvg` only removes the quotes of the first.
+const FONT_FAMILIES: &str = "sans-serif, DejaVu Sans, Noto Sans, \
+    Liberation Sans, Segoe UI, Helvetica";
+
+thread_local! {
+    /// Loading the system fonts takes a while, so it's only done when the
+    /// first text is rendered.
+    static OPTIONS: RefCell<Option<usvg::Options>> = const { RefCell::new(None) };
+}
+
+fn load_options() -> usvg::Options {
+    let mut options = usvg::Options::default();
+    options.fontdb.load_system_fonts();
+    if let Some(face) = options.fontdb.faces().first() {
+        options.font_family = face.family.clone();
+    }
+    options
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// How `render_text` draws the text. The lengths are in points.
+pub struct TextStyle {
+    pub color: [u8; 3],
+    /// Fills the whole image, including the padding.
+    pub background: [u8; 4],
+    pub font_size: f32,
+    /// The height of the image, the text is vertically centered.
+    pub height: f32,
+    /// The space left and right of the text.
+    pub padding: f32,
+}
+
+/// Renders a line of text into an image that's as wide as the text. Returns
+/// `None` if there's nothing to show or no font was found.
+pub fn render_text(text: &str, style: &TextStyle) -> Option<RgbaImage> {
+    if text.trim().is_empty() {
+        return None;
+    }
+    let font_size = style.font_size * IMAGE_PIXELS_PER_POINT;
+    let height = (style.height * IMAGE_PIXELS_PER_POINT).round();
+    let padding = (style.padding * IMAGE_PIXELS_PER_POINT).round();
+    // Wide enough for any character, the image is cropped to the text
+    let max_width =
+        (text.chars().count() as f32 + 1.0) * font_size * 2.0 + 2.0 * padding;
+    let [r, g, b] = style.color;
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\
+            <text x=\"{}\" y=\"{}\" font-family=\"{}\" font-size=\"{}\" \
+                fill=\"rgb({}, {}, {})\" xml:space=\"preserve\">{}</text>\
+        </svg>",
+        max_width,
+        height,
+        padding,
+        (height + font_size * 0.7) / 2.0,
+        FONT_FAMILIES,
+        font_size,
+        r,
+        g,
+        b,
+        escape(text)
+    );
+    OPTIONS.with(|options| {
+        let mut options = options.borrow_mut();
+        let options = options.get_or_insert_with(load_options);
+        let tree = usvg::Tree::from_str(&svg, &options.to_ref()).ok()?;
+        let bbox = tree.root.calculate_bbox()?;
+        let width = (bbox.right() as f32 + padding).ceil() as u32 + 1;
+        let width = width.min(max_width as u32);
+        let height = height as u32;
+        let mut pixmap = tiny_skia::Pixmap::new(width, height)?;
+        let [r, g, b, a] = style.background;
+        pixmap.fill(tiny_skia::Color::from_rgba8(r, g, b, a));
+        resvg::render(
+            &tree,
+            usvg::FitTo::Original,
+            tiny_skia::Transform::identity(),
+            pixmap.as_mut(),
+        )?;
+        // The pixmap is premultiplied, unlike the pictures
+        let data = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let c = pixel.demultiply();
+                [c.red(), c.green(), c.blue(), c.alpha()]
+            })
+            .collect();
+        RgbaImage::from_raw(width, height, data)
+    })
+}
@@ -21,6 +21,10 @@ pub fn request_exit() {
     EXIT_REQUESTED.store(true, Ordering::Relaxed);
 }
 
+pub fn exit_requested() -> bool {
+    EXIT_REQUESTED.load(Ordering::Relaxed)
+}
+
 /// Returns true if original was replaced by new
 fn aggregate_control_flow(
     original: &mut ControlFlow,
@@ -74,6 +78,7 @@ pub struct Application {
     windows: HashMap<WindowId, Rc<Window>>,
     global_handlers: Vec<ApplicationGlobalHandler>,
     at_exit: Option<Box<dyn FnOnce()>>,
+    close_handler: Option<Box<dyn FnMut() -> bool>>,
 }
 
 impl Application {
@@ -83,6 +88,7 @@ impl Application {
             windows: HashMap::new(),
             global_handlers: Vec::new(),
             at_exit: None,
+            close_handler: None,
         }
     }
 
@@ -93,6 +99,19 @@ impl Application {
         };
     }
 
+    /// The handler is called when a window is asked to be closed, and the
+    /// application only exits if it returns true. Without a handler the
+    /// application always exits.
+    pub fn set_close_handler<F: FnMut() -> bool + 'static>(
+        &mut self,
+        fun: Option<F>,
+    ) {
+        match fun {
+            Some(fun) => self.close_handler = Some(Box::new(fun)),
+            None => self.close_handler = None,
+        };
+    }
+
     pub fn register_window(&mut self, window: Rc<Window>) {
         self.windows.insert(window.get_id(), window);
     }
@@ -110,6 +129,7 @@ impl Application {
         let mut windows = self.windows;
         let mut at_exit = self.at_exit;
         let mut global_handlers = self.global_handlers;
+        let mut close_handler = self.close_handler;
         let mut control_flow_source = *windows.keys().next().unwrap();
         self.event_loop
             .run(move |event, _event_loop, control_flow| {
@@ -127,7 +147,13 @@ impl Application {
                         if let WindowEvent::CloseRequested = event {
                             // This actually wouldn't be okay for a general pupose ui toolkit,
                             // but gelatin is specifically made for emulsion so this is fine hehe
-                            request_exit();
+                            let exit = match close_handler {
+                                Some(ref mut close_handler) => close_handler(),
+                                None => true,
+                            };
+                            if exit {
+                                request_exit();
+                            }
                         }
                         let destroyed = matches!(event, WindowEvent::Destroyed);
                         windows.get(&window_id).unwrap().process_event(event);
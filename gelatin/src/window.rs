@@ -82,6 +82,10 @@ pub struct WindowDescriptor {
     #[builder(setter(into), default)]
     position: Option<PhysicalPosition<i32>>,
 
+    /// The smallest inner size the window may be resized to.
+    #[builder(setter(into), default)]
+    min_size: Option<PhysicalSize<u32>>,
+
     /// Only relevant on Wayland.
     /// See: https://docs.rs/winit/0.24.0/winit/platform/unix/trait.WindowBuilderExtUnix.html#tymethod.with_app_id
     #[builder(setter(into), default)]
@@ -147,6 +151,12 @@ impl Window {
             .with_window_icon(desc.icon)
             .with_visible(desc.position.is_none());
 
+        let window = if let Some(min_size) = desc.min_size {
+            window.with_min_inner_size(min_size)
+        } else {
+            window
+        };
+
         #[cfg(not(any(target_os = "macos", windows)))]
         let window = if let Some(app_id) = desc.app_id {
             window.with_app_id(app_id)